@@ -2,6 +2,14 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use utils::get_input_path;
+use utils::interval_set::{Interval, IntervalSet};
+
+fn parse_assignment(range: &str) -> IntervalSet {
+    let mut bounds = range.split('-');
+    let start = bounds.next().unwrap().parse().unwrap();
+    let end = bounds.next().unwrap().parse().unwrap();
+    IntervalSet::from(Interval::new(start, end))
+}
 
 fn run(input_file: &str) {
     let mut result: u32 = 0;
@@ -13,18 +21,10 @@ fn run(input_file: &str) {
         let line = line.unwrap();
 
         let mut split = line.split(",");
-        let left = split.next().unwrap();
-        let right = split.next().unwrap();
-        let mut ranges_left = left.split("-");
-        let mut ranges_right = right.split("-");
-        let start0 = ranges_left.next().unwrap().parse::<usize>().unwrap();
-        let end0 = ranges_left.next().unwrap().parse::<usize>().unwrap();
-        let start1 = ranges_right.next().unwrap().parse::<usize>().unwrap();
-        let end1 = ranges_right.next().unwrap().parse::<usize>().unwrap();
-
-        // println!("Line {}-{},{}-{}", &start0, &end0, &start1, &end1);
-
-        if (start0 <= start1 && end0 >= end1) || (start1 <= start0 && end1 >= end0) {
+        let left = parse_assignment(split.next().unwrap());
+        let right = parse_assignment(split.next().unwrap());
+
+        if left.intersect(&right).len() == left.len().min(right.len()) {
             result = result + 1;
         }
     }
@@ -42,21 +42,10 @@ fn run2(input_file: &str) {
         let line = line.unwrap();
 
         let mut split = line.split(",");
-        let left = split.next().unwrap();
-        let right = split.next().unwrap();
-        let mut ranges_left = left.split("-");
-        let mut ranges_right = right.split("-");
-        let start0 = ranges_left.next().unwrap().parse::<usize>().unwrap();
-        let end0 = ranges_left.next().unwrap().parse::<usize>().unwrap();
-        let start1 = ranges_right.next().unwrap().parse::<usize>().unwrap();
-        let end1 = ranges_right.next().unwrap().parse::<usize>().unwrap();
-
-        // println!("Line {}-{},{}-{}", &start0, &end0, &start1, &end1);
-
-        if (start0 >= start1 && start0 <= end1)
-            || (end0 >= start1 && end0 <= end1)
-            || (start1 >= start0 && start1 <= end0)
-        {
+        let left = parse_assignment(split.next().unwrap());
+        let right = parse_assignment(split.next().unwrap());
+
+        if !left.intersect(&right).is_empty() {
             result = result + 1;
         }
     }
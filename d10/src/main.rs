@@ -1,139 +1,402 @@
-use std::{
-    collections::HashSet,
-    fs::File,
-    io::{BufRead, BufReader},
-};
+use std::{collections::HashMap, fmt, fs};
 
 use utils::get_input_path;
 
-enum ExecutionState {
-    NeedInstruction,
-    ExecuteInstruction,
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Registers {
+    x: i64,
 }
 
-fn run(input_file: &str) {
-    // Init
-    let check_results_at = HashSet::from([20, 60, 100, 140, 180, 220]);
-    let max_cycles = 220;
+impl Default for Registers {
+    fn default() -> Self {
+        Registers { x: 1 }
+    }
+}
 
-    let file = File::open(input_file).unwrap();
-    let mut reader = BufReader::new(file);
+/// Applied to the registers once the last cycle of an instruction is over.
+type Effect = Box<dyn Fn(&mut Registers, &[i64])>;
 
-    let mut x = 1;
-    let mut final_result = 0;
-    let mut instruction = String::with_capacity(64);
-    let mut state = ExecutionState::NeedInstruction;
+struct InstructionDef {
+    arity: usize,
+    cycles: usize,
+    effect: Effect,
+}
 
-    for c in 1..max_cycles + 1 {
-        if check_results_at.contains(&c) {
-            let result = c * x;
-            println!(
-                "Current Value at {} is {} what results to {}",
-                &c, &x, &result
-            );
-            final_result += result;
-        }
-        match state {
-            ExecutionState::NeedInstruction => {
-                instruction.clear();
-                reader.read_line(&mut instruction).unwrap();
+#[derive(Debug, PartialEq, Eq)]
+enum CpuError {
+    DuplicateInstruction(String),
+    ZeroCycles(String),
+    UnknownInstruction {
+        line: usize,
+        mnemonic: String,
+    },
+    WrongArgumentCount {
+        line: usize,
+        expected: usize,
+        found: usize,
+    },
+    InvalidArgument {
+        line: usize,
+        argument: String,
+    },
+}
 
-                if !instruction.starts_with("noop") {
-                    state = ExecutionState::ExecuteInstruction;
-                }
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::DuplicateInstruction(mnemonic) => {
+                write!(f, "instruction {} is already defined", mnemonic)
             }
-            ExecutionState::ExecuteInstruction => {
-                let split: Vec<&str> = instruction.trim().split(" ").collect();
-                let number_string = split.get(1).unwrap();
-                let number: i64 = number_string.parse().unwrap();
-                x += number;
-                state = ExecutionState::NeedInstruction;
+            CpuError::ZeroCycles(mnemonic) => {
+                write!(f, "instruction {} has to take at least one cycle", mnemonic)
             }
-        };
+            CpuError::UnknownInstruction { line, mnemonic } => {
+                write!(f, "unknown instruction {} on line {}", mnemonic, line)
+            }
+            CpuError::WrongArgumentCount {
+                line,
+                expected,
+                found,
+            } => write!(
+                f,
+                "expected {} arguments on line {} but found {}",
+                expected, line, found
+            ),
+            CpuError::InvalidArgument { line, argument } => {
+                write!(f, "invalid argument {:?} on line {}", argument, line)
+            }
+        }
     }
-    println!("The final result is {}", final_result);
 }
 
-fn run2(input_file: &str) {
-    // Init
-    const COLUMNS: usize = 40;
-    const ROWS: usize = 6;
-    const MAX_CYCLES: usize = COLUMNS * ROWS;
-    let mut result_matrix = [0 as u8; MAX_CYCLES];
-
-    let file = File::open(input_file).unwrap();
-    let mut reader = BufReader::new(file);
-
-    let mut instruction = String::with_capacity(64);
-    let mut state = ExecutionState::NeedInstruction;
-    let mut sprite_position: i64 = 0;
-
-    // Execute
-    for c in 0..MAX_CYCLES {
-        let column = c % COLUMNS;
-
-        if sprite_position == column as i64
-            || (sprite_position + 1) == column as i64
-            || (sprite_position + 2) == column as i64
-        {
-            result_matrix[c] = 1;
-        } else {
-            result_matrix[c] = 0;
+struct InstructionSet {
+    definitions: Vec<InstructionDef>,
+    opcodes: HashMap<String, usize>,
+}
+
+impl InstructionSet {
+    fn new() -> Self {
+        InstructionSet {
+            definitions: Vec::new(),
+            opcodes: HashMap::new(),
         }
+    }
 
-        match state {
-            ExecutionState::NeedInstruction => {
-                instruction.clear();
-                reader.read_line(&mut instruction).unwrap();
+    /// `noop` takes one cycle, `addx V` two cycles and then adds V to X.
+    fn standard() -> Self {
+        let mut set = InstructionSet::new();
+        set.define("noop", 0, 1, |_, _| {}).unwrap();
+        set.define("addx", 1, 2, |registers, args| registers.x += args[0])
+            .unwrap();
+        set
+    }
 
-                if !instruction.starts_with("noop") {
-                    state = ExecutionState::ExecuteInstruction;
+    fn define(
+        &mut self,
+        mnemonic: &str,
+        arity: usize,
+        cycles: usize,
+        effect: impl Fn(&mut Registers, &[i64]) + 'static,
+    ) -> Result<(), CpuError> {
+        if self.opcodes.contains_key(mnemonic) {
+            return Err(CpuError::DuplicateInstruction(mnemonic.to_string()));
+        }
+        if cycles == 0 {
+            return Err(CpuError::ZeroCycles(mnemonic.to_string()));
+        }
+        self.opcodes
+            .insert(mnemonic.to_string(), self.definitions.len());
+        self.definitions.push(InstructionDef {
+            arity,
+            cycles,
+            effect: Box::new(effect),
+        });
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Instruction {
+    opcode: usize,
+    args: Vec<i64>,
+}
+
+/// State of the registers during a cycle, cycles are counted from 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct CycleState {
+    cycle: usize,
+    registers: Registers,
+}
+
+struct Cpu {
+    instruction_set: InstructionSet,
+    program: Vec<Instruction>,
+}
+
+impl Cpu {
+    fn new(instruction_set: InstructionSet) -> Self {
+        Cpu {
+            instruction_set,
+            program: Vec::new(),
+        }
+    }
+
+    /// Replaces the program by the one in `source`, one instruction per line.
+    fn load(&mut self, source: &str) -> Result<(), CpuError> {
+        let mut program = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let mut parts = line.split_whitespace();
+            let Some(mnemonic) = parts.next() else {
+                continue;
+            };
+            let opcode = *self.instruction_set.opcodes.get(mnemonic).ok_or_else(|| {
+                CpuError::UnknownInstruction {
+                    line: line_number,
+                    mnemonic: mnemonic.to_string(),
                 }
+            })?;
+            let args = parts
+                .map(|argument| {
+                    argument.parse().map_err(|_| CpuError::InvalidArgument {
+                        line: line_number,
+                        argument: argument.to_string(),
+                    })
+                })
+                .collect::<Result<Vec<i64>, CpuError>>()?;
+            let expected = self.instruction_set.definitions[opcode].arity;
+            if args.len() != expected {
+                return Err(CpuError::WrongArgumentCount {
+                    line: line_number,
+                    expected,
+                    found: args.len(),
+                });
             }
-            ExecutionState::ExecuteInstruction => {
-                let split: Vec<&str> = instruction.trim().split(" ").collect();
-                let number_string = split.get(1).unwrap();
-                let number: i64 = number_string.parse().unwrap();
-                sprite_position += number;
-                state = ExecutionState::NeedInstruction;
-            }
+            program.push(Instruction { opcode, args });
+        }
+        self.program = program;
+        Ok(())
+    }
+
+    /// Runs the program from the start, yielding one state per cycle until
+    /// the last instruction has finished.
+    fn cycles(&self) -> Cycles<'_> {
+        Cycles {
+            cpu: self,
+            registers: Registers::default(),
+            program_counter: 0,
+            remaining: 0,
+            cycle: 0,
+        }
+    }
+}
+
+struct Cycles<'a> {
+    cpu: &'a Cpu,
+    registers: Registers,
+    program_counter: usize,
+    /// Cycles left for the current instruction, 0 fetches the next one
+    remaining: usize,
+    cycle: usize,
+}
+
+impl Iterator for Cycles<'_> {
+    type Item = CycleState;
+
+    fn next(&mut self) -> Option<CycleState> {
+        let instruction = self.cpu.program.get(self.program_counter)?;
+        let definition = &self.cpu.instruction_set.definitions[instruction.opcode];
+        if self.remaining == 0 {
+            self.remaining = definition.cycles;
+        }
+
+        self.cycle += 1;
+        let state = CycleState {
+            cycle: self.cycle,
+            registers: self.registers,
         };
+
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            (definition.effect)(&mut self.registers, &instruction.args);
+            self.program_counter += 1;
+        }
+        Some(state)
     }
+}
+
+fn load_cpu(input_file: &str) -> Cpu {
+    let source = fs::read_to_string(input_file).unwrap();
+    let mut cpu = Cpu::new(InstructionSet::standard());
+    cpu.load(&source)
+        .unwrap_or_else(|error| panic!("Can not load {}: {}", input_file, error));
+    cpu
+}
+
+/// Sum of cycle times X at the given cycles.
+fn signal_strength(cycles: impl Iterator<Item = CycleState>, check_at: &[usize]) -> i64 {
+    cycles
+        .filter(|state| check_at.contains(&state.cycle))
+        .map(|state| {
+            let result = state.cycle as i64 * state.registers.x;
+            println!(
+                "Current Value at {} is {} what results to {}",
+                state.cycle, state.registers.x, result
+            );
+            result
+        })
+        .sum()
+}
 
-    // Display
-    for p in 0..MAX_CYCLES {
-        let symbol = if result_matrix[p] == 0 { '.' } else { '#' };
-        print!("{}", symbol);
-        if (p + 1) % COLUMNS == 0 {
-            println!();
+/// Each cycle draws one pixel, which is lit if the three pixel wide sprite
+/// centered on X covers the column.
+fn render_crt(cycles: impl Iterator<Item = CycleState>, columns: usize, rows: usize) -> String {
+    let mut pixels = vec!['.'; columns * rows];
+    for state in cycles.take(columns * rows) {
+        let position = state.cycle - 1;
+        let column = (position % columns) as i64;
+        if (state.registers.x - column).abs() <= 1 {
+            pixels[position] = '#';
         }
     }
+    pixels
+        .chunks(columns)
+        .map(|row| row.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn run(input_file: &str) -> i64 {
+    // Preamble
+    let check_results_at = [20, 60, 100, 140, 180, 220];
+
+    // Parse
+    let cpu = load_cpu(input_file);
+
+    // Solve
+    let final_result = signal_strength(cpu.cycles(), &check_results_at);
+
+    // Result
+    println!("The final result is {}", final_result);
+    final_result
+}
+
+fn run2(input_file: &str) -> String {
+    // Preamble
+    const COLUMNS: usize = 40;
+    const ROWS: usize = 6;
+
+    // Parse
+    let cpu = load_cpu(input_file);
+
+    // Solve
+    let image = render_crt(cpu.cycles(), COLUMNS, ROWS);
+
+    // Result
+    println!("{}", image);
+    image
 }
 
 fn main() {
     let input_path = get_input_path(file!());
     let input_file = input_path.to_str().unwrap();
 
-    run(&input_file);
-    run2(&input_file);
+    run(input_file);
+    run2(input_file);
 }
 
 #[cfg(test)]
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::render_crt;
     use crate::run;
     use crate::run2;
+    use crate::signal_strength;
+    use crate::Cpu;
+    use crate::CpuError;
+    use crate::InstructionSet;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 13140);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        assert_eq!(
+            run2(input_path.to_str().unwrap()),
+            [
+                "##..##..##..##..##..##..##..##..##..##..",
+                "###...###...###...###...###...###...###.",
+                "####....####....####....####....####....",
+                "#####.....#####.....#####.....#####.....",
+                "######......######......######......####",
+                "#######.......#######.......#######.....",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn test_cycles_of_a_small_program() {
+        let mut cpu = Cpu::new(InstructionSet::standard());
+        cpu.load("noop\naddx 3\naddx -5\n").unwrap();
+        let xs: Vec<(usize, i64)> = cpu
+            .cycles()
+            .map(|state| (state.cycle, state.registers.x))
+            .collect();
+        assert_eq!(xs, vec![(1, 1), (2, 1), (3, 1), (4, 4), (5, 4)]);
+        // The iterator restarts the program every time
+        assert_eq!(cpu.cycles().count(), 5);
+    }
+
+    #[test]
+    fn test_custom_instructions() {
+        let mut set = InstructionSet::standard();
+        set.define("mulx", 1, 3, |registers, args| registers.x *= args[0])
+            .unwrap();
+        set.define("setx", 1, 1, |registers, args| registers.x = args[0])
+            .unwrap();
+        assert_eq!(
+            set.define("noop", 0, 1, |_, _| {}),
+            Err(CpuError::DuplicateInstruction("noop".into()))
+        );
+        assert_eq!(
+            set.define("halt", 0, 0, |_, _| {}),
+            Err(CpuError::ZeroCycles("halt".into()))
+        );
+
+        let mut cpu = Cpu::new(set);
+        cpu.load("addx 2\nmulx 4\nsetx 30\nnoop").unwrap();
+        let xs: Vec<i64> = cpu.cycles().map(|state| state.registers.x).collect();
+        assert_eq!(xs, vec![1, 1, 3, 3, 3, 12, 30]);
+        assert_eq!(signal_strength(cpu.cycles(), &[3, 7]), 3 * 3 + 7 * 30);
+        assert_eq!(render_crt(cpu.cycles(), 4, 2), "####\n....");
+
+        assert_eq!(
+            cpu.load("noop\njmp 3"),
+            Err(CpuError::UnknownInstruction {
+                line: 2,
+                mnemonic: "jmp".into()
+            })
+        );
+        assert_eq!(
+            cpu.load("mulx 1 2"),
+            Err(CpuError::WrongArgumentCount {
+                line: 1,
+                expected: 1,
+                found: 2
+            })
+        );
+        assert_eq!(
+            cpu.load("addx x"),
+            Err(CpuError::InvalidArgument {
+                line: 1,
+                argument: "x".into()
+            })
+        );
     }
 }
@@ -1,42 +1,150 @@
-use eval::eval;
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::{Add, Mul};
+use std::str::FromStr;
 
+use num_bigint::BigUint;
+use num_traits::Zero;
 use utils::get_input_path;
 
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operand {
+    Old,
+    Constant(u64),
+}
+
+/// Right hand side of `new = ...`, always `old` combined with an operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Add(Operand),
+    Multiply(Operand),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum OperationError {
+    MissingAssignment(String),
+    UnsupportedSyntax(String),
+    UnsupportedOperator(String),
+    InvalidOperand(String),
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OperationError::MissingAssignment(text) => {
+                write!(f, "expected `new = ...` but got {:?}", text)
+            }
+            OperationError::UnsupportedSyntax(text) => {
+                write!(f, "expected `old <op> <operand>` but got {:?}", text)
+            }
+            OperationError::UnsupportedOperator(operator) => {
+                write!(f, "unsupported operator {:?}, only + and * are", operator)
+            }
+            OperationError::InvalidOperand(operand) => {
+                write!(f, "{:?} is neither `old` nor a number", operand)
+            }
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = OperationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "old" {
+            return Ok(Operand::Old);
+        }
+        s.parse()
+            .map(Operand::Constant)
+            .map_err(|_| OperationError::InvalidOperand(s.to_string()))
+    }
+}
+
+impl FromStr for Operation {
+    type Err = OperationError;
+
+    /// Parses `new = old * 19`, the operands of `+` and `*` may be swapped.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = s
+            .strip_prefix("new = ")
+            .ok_or_else(|| OperationError::MissingAssignment(s.to_string()))?;
+        let parts: Vec<&str> = expression.split_whitespace().collect();
+        let [left, operator, right] = parts[..] else {
+            return Err(OperationError::UnsupportedSyntax(expression.to_string()));
+        };
+
+        let (left, right): (Operand, Operand) = (left.parse()?, right.parse()?);
+        let operand = match (left, right) {
+            (Operand::Old, operand) | (operand, Operand::Old) => operand,
+            _ => return Err(OperationError::UnsupportedSyntax(expression.to_string())),
+        };
+        match operator {
+            "+" => Ok(Operation::Add(operand)),
+            "*" => Ok(Operation::Multiply(operand)),
+            _ => Err(OperationError::UnsupportedOperator(operator.to_string())),
+        }
+    }
+}
+
+impl Operation {
+    fn apply<T>(&self, old: &T) -> T
+    where
+        T: Clone + From<u64> + Add<Output = T> + Mul<Output = T>,
+    {
+        let value = |operand: &Operand| match operand {
+            Operand::Old => old.clone(),
+            Operand::Constant(constant) => T::from(*constant),
+        };
+        match self {
+            Operation::Add(operand) => old.clone() + value(operand),
+            Operation::Multiply(operand) => old.clone() * value(operand),
+        }
+    }
+}
+
+/// An item with the worry level it starts with, `id` is its position in the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Item {
+    pub id: usize,
+    pub worry: u64,
+}
+
 #[derive(Debug)]
 struct Monkey {
     pub id: usize,
-    pub items: VecDeque<u64>,
-    pub operation: String,
+    pub items: VecDeque<Item>,
+    pub operation: Operation,
     pub test_divisible: u64,
     pub test_false_to: usize,
     pub test_true_to: usize,
-    pub inspects: u64,
 }
 
-fn run(input_file: &str) {
-    // Preamble
-    const ROUNDS: usize = 20;
+fn parse_monkeys(input_file: &str) -> Vec<Monkey> {
     let mut monkeys: Vec<Monkey> = Vec::new();
+    let mut item_count = 0;
 
-    // Parse
     let file = File::open(input_file).unwrap();
     let reader = BufReader::new(file);
     let mut lines = reader.lines();
 
     while let Some(line_result) = lines.next() {
         let mut line = line_result.unwrap().trim().to_string();
-        let id: usize = line[7..8].parse().unwrap();
+        let id: usize = line[7..line.len() - 1].parse().unwrap();
         line = lines.next().unwrap().unwrap().trim().to_string();
         let splits = line[16..].split(", ");
-        let mut items: VecDeque<u64> = VecDeque::new();
+        let mut items: VecDeque<Item> = VecDeque::new();
         for item in splits {
-            items.push_back(item.parse().unwrap());
+            items.push_back(Item {
+                id: item_count,
+                worry: item.parse().unwrap(),
+            });
+            item_count += 1;
         }
-        let operation = lines.next().unwrap().unwrap().trim().to_string()[17..].to_string();
+        let operation = lines.next().unwrap().unwrap().trim().to_string()[11..]
+            .parse::<Operation>()
+            .unwrap_or_else(|error| panic!("Invalid operation of monkey {}: {}", id, error));
         let test_divisible: u64 = lines.next().unwrap().unwrap().trim().to_string()[19..]
             .parse()
             .unwrap();
@@ -47,176 +155,703 @@ fn run(input_file: &str) {
             .parse()
             .unwrap();
 
-        let monkey = Monkey {
+        monkeys.push(Monkey {
             id,
             items,
             operation,
             test_divisible,
             test_false_to,
             test_true_to,
-            inspects: 0,
-        };
-
-        monkeys.push(monkey);
+        });
         lines.next();
     }
+    monkeys
+}
 
-    // Solve
-    for _ in 0..ROUNDS {
-        for i in 0..monkeys.len() {
-            // for monkey in monkeys.iter_mut() {
-            while let Some(item) = monkeys.get_mut(i).unwrap().items.pop_front() {
-                monkeys.get_mut(i).unwrap().inspects += 1;
-                let division_test = monkeys
-                    .get(i)
-                    .unwrap()
-                    .operation
-                    .replace("old", &item.to_string());
-                let test_result = eval(&division_test).unwrap().as_u64().unwrap() / 3;
-                if test_result % monkeys.get(i).unwrap().test_divisible == 0 {
-                    let test_true_to = monkeys.get(i).unwrap().test_true_to;
-                    monkeys
-                        .get_mut(test_true_to)
-                        .unwrap()
-                        .items
-                        .push_back(test_result);
-                } else {
-                    let test_false_to = monkeys.get(i).unwrap().test_false_to;
-                    monkeys
-                        .get_mut(test_false_to)
-                        .unwrap()
-                        .items
-                        .push_back(test_result);
-                }
+/// What happens to the worry level after each inspection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Relief {
+    DivideBy(u64),
+    None,
+}
+
+/// How worry levels are kept small. Reducing modulo a multiple of every test
+/// divisor keeps the tests intact, `None` keeps the exact value as a big integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reduction {
+    Product,
+    Lcm,
+    None,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SimulationConfig {
+    rounds: usize,
+    relief: Relief,
+    reduction: Reduction,
+    /// Records every inspection of every item
+    track_items: bool,
+}
+
+impl SimulationConfig {
+    fn part1() -> Self {
+        SimulationConfig {
+            rounds: 20,
+            relief: Relief::DivideBy(3),
+            reduction: Reduction::None,
+            track_items: false,
+        }
+    }
+
+    fn part2() -> Self {
+        SimulationConfig {
+            rounds: 10000,
+            relief: Relief::None,
+            reduction: Reduction::Product,
+            track_items: false,
+        }
+    }
+}
+
+/// Worry level as stored by the engine.
+trait Worry: Clone {
+    fn from_start(value: u64) -> Self;
+    fn inspect(&self, operation: &Operation) -> Self;
+    fn relieve(self, relief: Relief) -> Self;
+    fn reduce(self, modulus: Option<u64>) -> Self;
+    fn is_divisible_by(&self, divisor: u64) -> bool;
+}
+
+impl Worry for u64 {
+    fn from_start(value: u64) -> Self {
+        value
+    }
+
+    fn inspect(&self, operation: &Operation) -> Self {
+        operation.apply(self)
+    }
+
+    fn relieve(self, relief: Relief) -> Self {
+        match relief {
+            Relief::DivideBy(divisor) => self / divisor,
+            Relief::None => self,
+        }
+    }
+
+    fn reduce(self, modulus: Option<u64>) -> Self {
+        modulus.map_or(self, |modulus| self % modulus)
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        self.is_multiple_of(divisor)
+    }
+}
+
+impl Worry for BigUint {
+    fn from_start(value: u64) -> Self {
+        BigUint::from(value)
+    }
+
+    fn inspect(&self, operation: &Operation) -> Self {
+        operation.apply(self)
+    }
+
+    fn relieve(self, relief: Relief) -> Self {
+        match relief {
+            Relief::DivideBy(divisor) => self / divisor,
+            Relief::None => self,
+        }
+    }
+
+    fn reduce(self, modulus: Option<u64>) -> Self {
+        match modulus {
+            Some(modulus) => self % modulus,
+            None => self,
+        }
+    }
+
+    fn is_divisible_by(&self, divisor: u64) -> bool {
+        (self % divisor).is_zero()
+    }
+}
+
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Product of the two highest inspection counts.
+fn monkey_business(inspections: &[u64]) -> u128 {
+    let mut inspections = inspections.to_vec();
+    inspections.sort_unstable_by(|a, b| b.cmp(a));
+    inspections
+        .iter()
+        .take(2)
+        .map(|count| *count as u128)
+        .product()
+}
+
+/// One inspection of a tracked item.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Inspection {
+    round: usize,
+    monkey: usize,
+    thrown_to: usize,
+}
+
+/// Inspections of every monkey, per round and in total.
+struct Simulation {
+    per_round: Vec<Vec<u64>>,
+    inspections: Vec<u64>,
+    /// Per item id, only filled when `track_items` is set
+    trajectories: Vec<Vec<Inspection>>,
+}
+
+impl Simulation {
+    fn monkey_business(&self) -> u128 {
+        monkey_business(&self.inspections)
+    }
+
+    /// One line per round and monkey with the inspections of that round and
+    /// the running total.
+    fn write_csv(&self, monkeys: &[Monkey], output_file: &str) -> std::io::Result<()> {
+        let mut writer = BufWriter::new(File::create(output_file)?);
+        writeln!(writer, "round,monkey,inspections,total")?;
+        let mut totals = vec![0; monkeys.len()];
+        for (round, inspections) in self.per_round.iter().enumerate() {
+            for (idx, monkey) in monkeys.iter().enumerate() {
+                totals[idx] += inspections[idx];
+                writeln!(
+                    writer,
+                    "{},{},{},{}",
+                    round + 1,
+                    monkey.id,
+                    inspections[idx],
+                    totals[idx]
+                )?;
             }
         }
+        writer.flush()
+    }
+}
+
+fn modulus_for(monkeys: &[Monkey], reduction: Reduction) -> Option<u64> {
+    let divisors = monkeys.iter().map(|monkey| monkey.test_divisible);
+    match reduction {
+        Reduction::Product => Some(divisors.product()),
+        Reduction::Lcm => Some(divisors.fold(1, |lcm, divisor| lcm / gcd(lcm, divisor) * divisor)),
+        Reduction::None => None,
     }
+}
 
-    // Result
-    monkeys.sort_by_key(|k| k.inspects);
+fn simulate(monkeys: &[Monkey], config: &SimulationConfig) -> Simulation {
+    let modulus = modulus_for(monkeys, config.reduction);
+    assert!(
+        modulus.is_none() || config.relief == Relief::None,
+        "Reducing worry levels modulo {:?} does not survive {:?}",
+        config.reduction,
+        config.relief
+    );
 
-    let result = monkeys.get(monkeys.len() - 1).unwrap().inspects
-        * monkeys.get(monkeys.len() - 2).unwrap().inspects;
+    match modulus {
+        Some(_) => simulate_with::<u64>(monkeys, config, modulus),
+        None => simulate_with::<BigUint>(monkeys, config, modulus),
+    }
+}
 
-    println!("{:?}", &monkeys);
+fn simulate_with<W: Worry>(
+    monkeys: &[Monkey],
+    config: &SimulationConfig,
+    modulus: Option<u64>,
+) -> Simulation {
+    let mut items: Vec<VecDeque<(usize, W)>> = monkeys
+        .iter()
+        .map(|monkey| {
+            monkey
+                .items
+                .iter()
+                .map(|item| (item.id, W::from_start(item.worry)))
+                .collect()
+        })
+        .collect();
+    let mut inspections = vec![0; monkeys.len()];
+    let mut per_round = Vec::with_capacity(config.rounds);
+    let mut trajectories = Vec::new();
+    if config.track_items {
+        let item_count = monkeys.iter().map(|monkey| monkey.items.len()).sum();
+        trajectories = vec![Vec::new(); item_count];
+    }
 
-    println!("Result is {}", result);
+    for round in 0..config.rounds {
+        let mut counts = vec![0; monkeys.len()];
+        for (i, monkey) in monkeys.iter().enumerate() {
+            while let Some((id, item)) = items[i].pop_front() {
+                counts[i] += 1;
+                let worry = item
+                    .inspect(&monkey.operation)
+                    .relieve(config.relief)
+                    .reduce(modulus);
+                let to = if worry.is_divisible_by(monkey.test_divisible) {
+                    monkey.test_true_to
+                } else {
+                    monkey.test_false_to
+                };
+                if config.track_items {
+                    trajectories[id].push(Inspection {
+                        round: round + 1,
+                        monkey: monkey.id,
+                        thrown_to: monkeys[to].id,
+                    });
+                }
+                items[to].push_back((id, worry));
+            }
+        }
+        for (total, count) in inspections.iter_mut().zip(&counts) {
+            *total += count;
+        }
+        per_round.push(counts);
+    }
+
+    Simulation {
+        per_round,
+        inspections,
+        trajectories,
+    }
 }
 
-fn run2(input_file: &str) {
-    // Preamble
-    const ROUNDS: usize = 10000;
-    let mut monkeys: Vec<Monkey> = Vec::new();
-    let mut comman_divisor = 1;
+/// Where an item is at the start of a round. Items never interact, so with
+/// worry levels kept modulo a multiple of the test divisors the state space is
+/// finite and every item eventually repeats a state.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct ItemState {
+    monkey: usize,
+    worry: u64,
+}
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-    let mut lines = reader.lines();
+/// Rounds of a single item, from round `start` on they repeat every `length`
+/// rounds. `inspected[round]` are the monkeys the item visits in that round.
+#[derive(Debug)]
+struct ItemCycle {
+    item: usize,
+    start: usize,
+    length: usize,
+    inspected: Vec<Vec<usize>>,
+}
 
-    while let Some(line_result) = lines.next() {
-        let mut line = line_result.unwrap().trim().to_string();
-        let id: usize = line[7..8].parse().unwrap();
-        line = lines.next().unwrap().unwrap().trim().to_string();
-        let splits = line[16..].split(", ");
-        let mut items: VecDeque<u64> = VecDeque::new();
-        for item in splits {
-            items.push_back(item.parse().unwrap());
+impl ItemCycle {
+    /// Inspections per monkey index over the first `rounds` rounds.
+    fn inspections(&self, monkey_count: usize, rounds: u64) -> Vec<u64> {
+        let mut counts = vec![0; monkey_count];
+        let mut count_rounds = |range: std::ops::Range<usize>, times: u64| {
+            for inspected in &self.inspected[range] {
+                for monkey in inspected {
+                    counts[*monkey] += times;
+                }
+            }
+        };
+
+        let prefix = (rounds as usize).min(self.start);
+        count_rounds(0..prefix, 1);
+        if rounds > self.start as u64 {
+            let rest = rounds - self.start as u64;
+            let (full, partial) = (rest / self.length as u64, rest % self.length as u64);
+            count_rounds(self.start..self.start + self.length, full);
+            count_rounds(self.start..self.start + partial as usize, 1);
         }
-        let operation = lines.next().unwrap().unwrap().trim().to_string()[17..].to_string();
-        let test_divisible: u64 = lines.next().unwrap().unwrap().trim().to_string()[19..]
-            .parse()
-            .unwrap();
-        let test_true_to: usize = lines.next().unwrap().unwrap().trim().to_string()[25..]
-            .parse()
-            .unwrap();
-        let test_false_to: usize = lines.next().unwrap().unwrap().trim().to_string()[26..]
-            .parse()
-            .unwrap();
+        counts
+    }
+}
 
-        comman_divisor *= test_divisible;
-        let monkey = Monkey {
-            id,
-            items,
-            operation,
-            test_divisible,
-            test_false_to,
-            test_true_to,
-            inspects: 0,
+/// A round as seen by one item, it keeps being inspected as long as it is
+/// thrown to monkeys whose turn is still to come.
+fn item_round(monkeys: &[Monkey], state: ItemState, modulus: u64) -> (Vec<usize>, ItemState) {
+    let mut inspected = Vec::new();
+    let (mut idx, mut worry) = (state.monkey, state.worry);
+    loop {
+        inspected.push(idx);
+        let monkey = &monkeys[idx];
+        worry = (monkey.operation.apply(&(worry as u128)) % modulus as u128) as u64;
+        let to = if worry.is_multiple_of(monkey.test_divisible) {
+            monkey.test_true_to
+        } else {
+            monkey.test_false_to
         };
+        if to < idx {
+            return (inspected, ItemState { monkey: to, worry });
+        }
+        idx = to;
+    }
+}
 
-        monkeys.push(monkey);
-        lines.next();
+fn find_item_cycle(monkeys: &[Monkey], item: usize, state: ItemState, modulus: u64) -> ItemCycle {
+    let mut seen: HashMap<ItemState, usize> = HashMap::new();
+    let mut inspected = Vec::new();
+    let mut state = state;
+    loop {
+        if let Some(start) = seen.insert(state, inspected.len()) {
+            return ItemCycle {
+                item,
+                start,
+                length: inspected.len() - start,
+                inspected,
+            };
+        }
+        let (visited, next) = item_round(monkeys, state, modulus);
+        inspected.push(visited);
+        state = next;
     }
+}
 
-    // Solve
-    for _ in 0..ROUNDS {
-        for i in 0..monkeys.len() {
-            // for monkey in monkeys.iter_mut() {
-            while let Some(item) = monkeys.get_mut(i).unwrap().items.pop_front() {
-                monkeys.get_mut(i).unwrap().inspects += 1;
-                let division_test = monkeys
-                    .get(i)
-                    .unwrap()
-                    .operation
-                    .replace("old", &item.to_string());
-                let test_result = eval(&division_test).unwrap().as_u64().unwrap() % comman_divisor;
-                if test_result % monkeys.get(i).unwrap().test_divisible == 0 {
-                    let test_true_to = monkeys.get(i).unwrap().test_true_to;
-                    monkeys
-                        .get_mut(test_true_to)
-                        .unwrap()
-                        .items
-                        .push_back(test_result);
-                } else {
-                    let test_false_to = monkeys.get(i).unwrap().test_false_to;
-                    monkeys
-                        .get_mut(test_false_to)
-                        .unwrap()
-                        .items
-                        .push_back(test_result);
+/// Inspections per monkey after `rounds` rounds without relief, summed over
+/// the cycle of every item instead of simulating each round.
+fn extrapolate_inspections(
+    monkeys: &[Monkey],
+    reduction: Reduction,
+    rounds: u64,
+) -> (Vec<u64>, Vec<ItemCycle>) {
+    let modulus = modulus_for(monkeys, reduction)
+        .expect("Extrapolating needs worry levels reduced by a modulus");
+    let mut cycles = Vec::new();
+    let mut inspections = vec![0; monkeys.len()];
+    for (idx, monkey) in monkeys.iter().enumerate() {
+        for item in &monkey.items {
+            let state = ItemState {
+                monkey: idx,
+                worry: item.worry % modulus,
+            };
+            let cycle = find_item_cycle(monkeys, item.id, state, modulus);
+            let counts = cycle.inspections(monkeys.len(), rounds);
+            for (total, count) in inspections.iter_mut().zip(counts) {
+                *total += count;
+            }
+            cycles.push(cycle);
+        }
+    }
+    (inspections, cycles)
+}
+
+#[derive(Default)]
+struct Options {
+    /// Replaces the puzzle parts by a single simulation when set
+    custom: Option<SimulationConfig>,
+    /// Where to write the per round inspection statistics
+    csv_file: Option<String>,
+    /// Item whose path between the monkeys is printed
+    trace_item: Option<usize>,
+    /// Round count to extrapolate part 2 to from the item cycles
+    extrapolate: Option<u64>,
+}
+
+impl Options {
+    fn from_args() -> Self {
+        let mut config = SimulationConfig::part2();
+        let mut custom = false;
+        let mut csv_file = None;
+        let mut trace_item = None;
+        let mut extrapolate = None;
+        let mut reduction = None;
+        for arg in std::env::args().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                "--rounds" => config.rounds = value.parse().expect("Invalid --rounds"),
+                "--relief" => {
+                    config.relief = match value {
+                        "none" => Relief::None,
+                        _ => match value.parse().expect("Invalid --relief") {
+                            0 => panic!("--relief can not divide by 0"),
+                            divisor => Relief::DivideBy(divisor),
+                        },
+                    }
+                }
+                "--modulus" => {
+                    reduction = Some(match value {
+                        "product" => Reduction::Product,
+                        "lcm" => Reduction::Lcm,
+                        "none" => Reduction::None,
+                        _ => panic!("Unknown modulus {}", value),
+                    })
+                }
+                "--csv" => {
+                    csv_file = Some(value.to_string());
+                    continue;
                 }
+                "--trace" => {
+                    trace_item = Some(value.parse().expect("Invalid --trace"));
+                    continue;
+                }
+                "--extrapolate" => {
+                    extrapolate = Some(value.parse().expect("Invalid --extrapolate"));
+                    continue;
+                }
+                _ => panic!("Unknown argument {}", arg),
             }
+            custom = true;
         }
+        // Reducing modulo the divisors breaks once the worry is divided
+        config.reduction = match (config.relief, reduction) {
+            (Relief::None, Some(reduction)) => reduction,
+            (Relief::DivideBy(_), Some(Reduction::Product | Reduction::Lcm)) => {
+                panic!("--modulus only works together with --relief=none")
+            }
+            (Relief::DivideBy(_), _) => Reduction::None,
+            (Relief::None, None) => config.reduction,
+        };
+        Options {
+            custom: custom.then_some(config),
+            csv_file,
+            trace_item,
+            extrapolate,
+        }
+    }
+}
+
+fn print_trajectory(trajectory: &[Inspection], item: usize) {
+    println!("Item {} was inspected {} times", item, trajectory.len());
+    for inspection in trajectory {
+        println!(
+            "    round {}: monkey {} throws it to monkey {}",
+            inspection.round, inspection.monkey, inspection.thrown_to
+        );
+    }
+}
+
+fn solve(input_file: &str, config: &SimulationConfig, options: &Options) -> u128 {
+    // Parse
+    let monkeys = parse_monkeys(input_file);
+
+    // Solve
+    let config = SimulationConfig {
+        track_items: config.track_items || options.trace_item.is_some(),
+        ..*config
+    };
+    let simulation = simulate(&monkeys, &config);
+    if let Some(csv_file) = &options.csv_file {
+        simulation
+            .write_csv(&monkeys, csv_file)
+            .unwrap_or_else(|error| panic!("Could not write {}: {}", csv_file, error));
+        println!("Inspection statistics written to {}", csv_file);
+    }
+    if let Some(item) = options.trace_item {
+        let trajectory = simulation
+            .trajectories
+            .get(item)
+            .unwrap_or_else(|| panic!("There is no item {}", item));
+        print_trajectory(trajectory, item);
     }
 
     // Result
-    monkeys.sort_by_key(|k| k.inspects);
+    let result = simulation.monkey_business();
+    println!("Inspections: {:?}", simulation.inspections);
+    println!("Result is {}", result);
+    result
+}
 
-    let result = monkeys.get(monkeys.len() - 1).unwrap().inspects
-        * monkeys.get(monkeys.len() - 2).unwrap().inspects;
+fn run(input_file: &str) -> u128 {
+    solve(input_file, &SimulationConfig::part1(), &Options::default())
+}
 
-    println!("{:?}", &monkeys);
+fn run2(input_file: &str, options: &Options) -> u128 {
+    solve(input_file, &SimulationConfig::part2(), options)
+}
+
+fn run_extrapolated(input_file: &str, rounds: u64) -> u128 {
+    // Parse
+    let monkeys = parse_monkeys(input_file);
 
+    // Solve
+    let (inspections, cycles) = extrapolate_inspections(&monkeys, Reduction::Product, rounds);
+    for cycle in &cycles {
+        println!(
+            "Item {} repeats every {} rounds after round {}",
+            cycle.item, cycle.length, cycle.start
+        );
+    }
+
+    // Result
+    let result = monkey_business(&inspections);
+    println!("Inspections after {} rounds: {:?}", rounds, inspections);
     println!("Result is {}", result);
+    result
 }
 
 fn main() {
     let input_path = get_input_path(file!());
     let input_file = input_path.to_str().unwrap();
+    let options = Options::from_args();
 
     println!("{:?}", input_file);
 
-    run(&input_file);
-    run2(&input_file);
+    match options.custom {
+        Some(config) => {
+            solve(input_file, &config, &options);
+        }
+        None => {
+            run(input_file);
+            run2(input_file, &options);
+        }
+    }
+    if let Some(rounds) = options.extrapolate {
+        run_extrapolated(input_file, rounds);
+    }
 }
 
 #[cfg(test)]
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::extrapolate_inspections;
+    use crate::parse_monkeys;
     use crate::run;
     use crate::run2;
+    use crate::simulate;
+    use crate::Operand;
+    use crate::Operation;
+    use crate::OperationError;
+    use crate::Options;
+    use crate::Reduction;
+    use crate::Relief;
+    use crate::SimulationConfig;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 10605);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        assert_eq!(
+            run2(input_path.to_str().unwrap(), &Options::default()),
+            2713310158
+        );
+    }
+
+    #[test]
+    fn test_parse_operations() {
+        let parse = |s: &str| s.parse::<Operation>();
+        assert_eq!(
+            parse("new = old * 19"),
+            Ok(Operation::Multiply(Operand::Constant(19)))
+        );
+        assert_eq!(
+            parse("new = old * old"),
+            Ok(Operation::Multiply(Operand::Old))
+        );
+        assert_eq!(
+            parse("new = 3 + old"),
+            Ok(Operation::Add(Operand::Constant(3)))
+        );
+        assert_eq!(parse("new = old * old").unwrap().apply(&12u64), 144);
+        assert_eq!(parse("new = old + 6").unwrap().apply(&12u64), 18);
+
+        assert_eq!(
+            parse("new = old - 3"),
+            Err(OperationError::UnsupportedOperator("-".to_string()))
+        );
+        assert_eq!(
+            parse("new = 2 * 3"),
+            Err(OperationError::UnsupportedSyntax("2 * 3".to_string()))
+        );
+        assert_eq!(
+            parse("new = old * x"),
+            Err(OperationError::InvalidOperand("x".to_string()))
+        );
+        assert_eq!(
+            parse("new = (old + 1) * 2"),
+            Err(OperationError::UnsupportedSyntax(
+                "(old + 1) * 2".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("old * 2"),
+            Err(OperationError::MissingAssignment("old * 2".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_reductions_agree_and_export_csv() {
+        let input_path = get_test_input_path(file!());
+        let monkeys = parse_monkeys(input_path.to_str().unwrap());
+
+        let inspections: Vec<Vec<u64>> = [Reduction::Product, Reduction::Lcm, Reduction::None]
+            .into_iter()
+            .map(|reduction| {
+                let config = SimulationConfig {
+                    rounds: 20,
+                    relief: Relief::None,
+                    reduction,
+                    track_items: false,
+                };
+                simulate(&monkeys, &config).inspections
+            })
+            .collect();
+        assert_eq!(inspections[0], vec![99, 97, 8, 103]);
+        assert_eq!(inspections[0], inspections[1]);
+        assert_eq!(inspections[0], inspections[2]);
+
+        let simulation = simulate(&monkeys, &SimulationConfig::part1());
+        let output_file = std::env::temp_dir().join("d11_stats.csv");
+        simulation
+            .write_csv(&monkeys, output_file.to_str().unwrap())
+            .unwrap();
+        let csv = std::fs::read_to_string(&output_file).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 1 + 20 * 4);
+        assert_eq!(lines[0], "round,monkey,inspections,total");
+        assert_eq!(lines[1], "1,0,2,2");
+        assert_eq!(lines[80], "20,3,6,105");
+    }
+
+    #[test]
+    fn test_item_trajectories_and_extrapolation() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        let monkeys = parse_monkeys(input_file);
+
+        let config = SimulationConfig {
+            track_items: true,
+            ..SimulationConfig::part2()
+        };
+        let simulation = simulate(&monkeys, &config);
+        assert_eq!(simulation.trajectories.len(), 10);
+        let total: usize = simulation.trajectories.iter().map(Vec::len).sum();
+        assert_eq!(total as u64, simulation.inspections.iter().sum::<u64>());
+
+        // Item 0 starts with monkey 0 at worry 79, which goes to 500 / 3 in
+        // part 1 but stays 1501 without relief and is thrown to monkey 3
+        let first = simulation.trajectories[0][0];
+        assert_eq!((first.round, first.monkey, first.thrown_to), (1, 0, 3));
+        for trajectory in &simulation.trajectories {
+            for inspections in trajectory.windows(2) {
+                assert!(inspections[0].round <= inspections[1].round);
+                assert_eq!(inspections[0].thrown_to, inspections[1].monkey);
+            }
+        }
+
+        for rounds in [1, 20, 1000, 10000] {
+            let config = SimulationConfig {
+                rounds: rounds as usize,
+                ..SimulationConfig::part2()
+            };
+            let (inspections, _) = extrapolate_inspections(&monkeys, Reduction::Product, rounds);
+            assert_eq!(inspections, simulate(&monkeys, &config).inspections);
+        }
+
+        // Far beyond what can be simulated the counts keep their per round rate
+        let rounds = 1_000_000_000_000;
+        let (many, _) = extrapolate_inspections(&monkeys, Reduction::Lcm, rounds);
+        assert_eq!(
+            many,
+            extrapolate_inspections(&monkeys, Reduction::Product, rounds).0
+        );
+        let (few, _) = extrapolate_inspections(&monkeys, Reduction::Lcm, 10000);
+        for (many, few) in many.iter().zip(few) {
+            let rate = *many as f64 / rounds as f64;
+            assert!((rate - few as f64 / 10000.0).abs() < 0.01);
+        }
     }
 }
@@ -1,98 +1,209 @@
-#![feature(array_windows)]
-#![feature(array_chunks)]
-
+use std::cmp::Ordering;
+use std::fmt;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::str::FromStr;
 
-use serde_json::{json, Value};
 use utils::get_input_path;
 
-fn check_packages(package1: &Value, package2: &Value) -> i8 {
-    // println!("{} vs {}", package1, package2);
-    let max_length = package1
-        .as_array()
-        .unwrap()
-        .len()
-        .min(package2.as_array().unwrap().len());
-
-    for i in 0..max_length {
-        let ipl = package1.get(i).unwrap();
-        let ipr = package2.get(i).unwrap();
-
-        if ipl.is_array() || ipr.is_array() {
-            let to_use_ipl = if ipl.is_array() {
-                ipl.clone()
-            } else {
-                json!([ipl.as_i64()])
-            };
+#[derive(Clone, Debug)]
+enum Packet {
+    Int(u32),
+    List(Vec<Packet>),
+}
 
-            let to_use_ipr = if ipr.is_array() {
-                ipr.clone()
-            } else {
-                json!([ipr.as_i64()])
-            };
+#[derive(Debug, PartialEq, Eq)]
+enum PacketError {
+    UnexpectedEnd,
+    UnexpectedCharacter { position: usize, character: char },
+    NumberTooLarge { position: usize },
+    TrailingInput { position: usize },
+}
+
+impl fmt::Display for PacketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PacketError::UnexpectedEnd => write!(f, "packet ends unexpectedly"),
+            PacketError::UnexpectedCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "unexpected character {:?} at position {}",
+                character, position
+            ),
+            PacketError::NumberTooLarge { position } => {
+                write!(f, "number at position {} is too large", position)
+            }
+            PacketError::TrailingInput { position } => {
+                write!(
+                    f,
+                    "unexpected input after the packet at position {}",
+                    position
+                )
+            }
+        }
+    }
+}
 
-            let zr = check_packages(&to_use_ipl, &to_use_ipr);
+/// Recursive descent over the bytes of a single line.
+struct PacketParser<'a> {
+    input: &'a [u8],
+    position: usize,
+}
+
+impl<'a> PacketParser<'a> {
+    fn peek(&self) -> Result<u8, PacketError> {
+        self.input
+            .get(self.position)
+            .copied()
+            .ok_or(PacketError::UnexpectedEnd)
+    }
+
+    fn unexpected(&self) -> PacketError {
+        PacketError::UnexpectedCharacter {
+            position: self.position,
+            character: self.input[self.position] as char,
+        }
+    }
+
+    fn packet(&mut self) -> Result<Packet, PacketError> {
+        match self.peek()? {
+            b'[' => self.list(),
+            b'0'..=b'9' => self.int(),
+            _ => Err(self.unexpected()),
+        }
+    }
+
+    fn int(&mut self) -> Result<Packet, PacketError> {
+        let start = self.position;
+        let mut value: u32 = 0;
+        while let Ok(byte @ b'0'..=b'9') = self.peek() {
+            value = value
+                .checked_mul(10)
+                .and_then(|value| value.checked_add((byte - b'0') as u32))
+                .ok_or(PacketError::NumberTooLarge { position: start })?;
+            self.position += 1;
+        }
+        Ok(Packet::Int(value))
+    }
 
-            if zr == -1 || zr == 1 {
-                return zr;
+    fn list(&mut self) -> Result<Packet, PacketError> {
+        // Skip the opening bracket
+        self.position += 1;
+        let mut items = Vec::new();
+        if self.peek()? == b']' {
+            self.position += 1;
+            return Ok(Packet::List(items));
+        }
+        loop {
+            items.push(self.packet()?);
+            match self.peek()? {
+                b',' => self.position += 1,
+                b']' => {
+                    self.position += 1;
+                    return Ok(Packet::List(items));
+                }
+                _ => return Err(self.unexpected()),
             }
         }
+    }
+}
 
-        let iplv = if ipl.is_number() {
-            ipl.as_i64().unwrap()
-        } else {
-            i64::MAX
-        };
+impl FromStr for Packet {
+    type Err = PacketError;
 
-        let iprv = if ipr.is_number() {
-            ipr.as_i64().unwrap()
-        } else {
-            i64::MAX
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = PacketParser {
+            input: s.as_bytes(),
+            position: 0,
         };
-
-        if iplv > iprv {
-            return -1;
+        let packet = parser.packet()?;
+        if parser.position != s.len() {
+            return Err(PacketError::TrailingInput {
+                position: parser.position,
+            });
         }
+        Ok(packet)
+    }
+}
 
-        if iplv < iprv {
-            return 1;
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Packet::Int(value) => write!(f, "{}", value),
+            Packet::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
         }
     }
+}
 
-    if package1.as_array().unwrap().len() > package2.as_array().unwrap().len() {
-        return -1;
+/// Integers compare by value, lists element by element and then by length.
+/// An integer compared with a list is treated as a list holding just it.
+impl Ord for Packet {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Packet::Int(a), Packet::Int(b)) => a.cmp(b),
+            (Packet::List(a), Packet::List(b)) => a.cmp(b),
+            (Packet::Int(_), Packet::List(b)) => std::slice::from_ref(self).cmp(b),
+            (Packet::List(a), Packet::Int(_)) => a.as_slice().cmp(std::slice::from_ref(other)),
+        }
     }
+}
 
-    if package1.as_array().unwrap().len() < package2.as_array().unwrap().len() {
-        return 1;
+/// Equality follows the ordering, so `[[2]]` equals `[2]` and `2`.
+impl PartialEq for Packet {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
     }
+}
+
+impl Eq for Packet {}
 
-    return 0;
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-fn run(input_file: &str) {
-    // Parse
+fn parse_packets(input_file: &str) -> Vec<Packet> {
     let file = File::open(input_file).unwrap();
     let reader = BufReader::new(file);
-    let mut values: Vec<Value> = Vec::new();
 
-    for line in reader.lines() {
+    let mut packets = Vec::new();
+    for (index, line) in reader.lines().enumerate() {
         let line = line.unwrap().trim().to_string();
         if line.is_empty() {
             continue;
         }
-
-        let value = serde_json::from_str(&line).unwrap();
-        values.push(value);
+        match line.parse() {
+            Ok(packet) => packets.push(packet),
+            Err(error) => panic!("Malformed packet on line {}: {}", index + 1, error),
+        }
     }
+    packets
+}
+
+fn run(input_file: &str) -> usize {
+    // Parse
+    let packets = parse_packets(input_file);
 
     // Solve
     let mut in_order: usize = 0;
-    for (idx, [package1, package2]) in values.array_chunks().enumerate() {
+    for (idx, pair) in packets.chunks(2).enumerate() {
+        let [package1, package2] = pair else {
+            panic!("Packet {} has no partner", pair[0]);
+        };
         print!("{} vs {}", package1, package2);
-        let check_result = check_packages(package1, package2);
-        if check_result == 1 {
+        if package1 < package2 {
             in_order += idx + 1;
             println!(" is in order");
         } else {
@@ -101,60 +212,110 @@ fn run(input_file: &str) {
     }
     // Result
     println!("Result: {}", in_order);
+    in_order
 }
 
-fn run2(input_file: &str) {
-    // Preamble
-    let package_with_two = json!([[2]]);
-    let package_with_six = json!([[6]]);
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DecoderStrategy {
+    /// Counts the packets smaller than each divider, linear time
+    RankCount,
+    /// Sorts every packet together with the dividers
+    Sort,
+}
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-    let mut values: Vec<Value> = Vec::new();
+struct Options {
+    strategy: DecoderStrategy,
+    /// Writes the sorted packets including the dividers to this file
+    sorted_output: Option<String>,
+}
 
-    for line in reader.lines() {
-        let line = line.unwrap().trim().to_string();
-        if line.is_empty() {
-            continue;
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            strategy: DecoderStrategy::RankCount,
+            sorted_output: None,
         }
+    }
+}
 
-        let value = serde_json::from_str(&line).unwrap();
-        values.push(value);
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        for arg in std::env::args().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                "--sort" => options.strategy = DecoderStrategy::Sort,
+                "--sorted-output" => options.sorted_output = Some(value.to_string()),
+                _ => panic!("Unknown argument {}", arg),
+            }
+        }
+        options
     }
+}
 
-    values.push(package_with_two.clone());
-    values.push(package_with_six.clone());
+fn write_sorted_packets(packets: &[Packet], output_file: &str) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(output_file)?);
+    for (idx, packet) in packets.iter().enumerate() {
+        writeln!(writer, "{}: {}", idx + 1, packet)?;
+    }
+    writer.flush()
+}
 
-    // Solve
-    values.sort_unstable_by(|p1, p2|{
-        match check_packages(p1, p2) {
-            1 => std::cmp::Ordering::Less,
-            -1 => std::cmp::Ordering::Greater,
-            _ => std::cmp::Ordering::Equal,
-        }
-    });
+/// Sorts the packets and puts each divider in front of the packets equal to it.
+fn insert_dividers<'a>(packets: &mut Vec<Packet>, dividers: impl IntoIterator<Item = &'a Packet>) {
+    packets.sort();
+    for divider in dividers {
+        let idx = packets.partition_point(|p| p < divider);
+        packets.insert(idx, divider.clone());
+    }
+}
 
-    let mut package_with_two_idx = 0;
-    let mut package_with_six_idx = 0;
-    for (idx, value) in values.iter().enumerate() {
-        if *value == package_with_two {
-            package_with_two_idx = idx + 1;
-        }
+fn run2(input_file: &str, options: &Options) -> usize {
+    // Preamble
+    let package_with_two: Packet = "[[2]]".parse().unwrap();
+    let package_with_six: Packet = "[[6]]".parse().unwrap();
+
+    // Parse
+    let mut packets = parse_packets(input_file);
 
-        if *value == package_with_six {
-            package_with_six_idx = idx + 1;
+    // Solve
+    // A divider goes in front of the packets that order equal to it, e.g. [[2]]
+    // before [2], both when counting and when sorting
+    let (package_with_two_idx, package_with_six_idx) = match options.strategy {
+        DecoderStrategy::RankCount => {
+            // [[2]] itself comes before [[6]], which moves it one further back
+            let smaller_than = |divider: &Packet| packets.iter().filter(|p| *p < divider).count();
+            (
+                smaller_than(&package_with_two) + 1,
+                smaller_than(&package_with_six) + 2,
+            )
+        }
+        DecoderStrategy::Sort => {
+            insert_dividers(&mut packets, [&package_with_two, &package_with_six]);
+
+            // Packets like [2] equal [[2]], so search for the first one not
+            // smaller instead of any equal one
+            let position_of = |divider: &Packet| packets.partition_point(|p| p < divider) + 1;
+            (
+                position_of(&package_with_two),
+                position_of(&package_with_six),
+            )
         }
+    };
 
-        if package_with_six_idx != 0 && package_with_two_idx != 0 {
-            break;
+    if let Some(output_file) = &options.sorted_output {
+        if options.strategy == DecoderStrategy::RankCount {
+            insert_dividers(&mut packets, [&package_with_two, &package_with_six]);
         }
+        write_sorted_packets(&packets, output_file)
+            .unwrap_or_else(|error| panic!("Could not write {}: {}", output_file, error));
+        println!("Sorted packets written to {}", output_file);
     }
 
-    
     // Result
     let decoder_key = package_with_two_idx * package_with_six_idx;
     println!("Decoder key for the distress signal: {}", decoder_key);
+    decoder_key
 }
 
 fn main() {
@@ -163,26 +324,119 @@ fn main() {
 
     println!("{:?}", input_file);
 
-    run(&input_file);
-    run2(&input_file);
+    let options = Options::from_args();
+
+    run(input_file);
+    run2(input_file, &options);
 }
 
 #[cfg(test)]
 mod main_test {
+    use utils::get_test_input_2_path;
     use utils::get_test_input_path;
 
     use crate::run;
     use crate::run2;
+    use crate::DecoderStrategy;
+    use crate::Options;
+    use crate::Packet;
+    use crate::PacketError;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 13);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        for strategy in [DecoderStrategy::RankCount, DecoderStrategy::Sort] {
+            let options = Options {
+                strategy,
+                sorted_output: None,
+            };
+            assert_eq!(run2(input_path.to_str().unwrap(), &options), 140);
+        }
+    }
+
+    #[test]
+    fn test_dividers_come_before_equal_packets() {
+        // [2] and [[2]] order equal to the divider [[2]], [6] to [[6]]
+        let input_path = get_test_input_2_path(file!());
+        let output_file = std::env::temp_dir().join("d13_sorted_ties.txt");
+        for strategy in [DecoderStrategy::RankCount, DecoderStrategy::Sort] {
+            let options = Options {
+                strategy,
+                sorted_output: Some(output_file.to_str().unwrap().to_string()),
+            };
+            assert_eq!(run2(input_path.to_str().unwrap(), &options), 2 * 7);
+
+            let sorted = std::fs::read_to_string(&output_file).unwrap();
+            let lines: Vec<&str> = sorted.lines().collect();
+            assert_eq!(
+                lines,
+                [
+                    "1: [1]", "2: [[2]]", "3: [2]", "4: [[2]]", "5: [3]", "6: [5]", "7: [[6]]",
+                    "8: [6]"
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_and_compare_packets() {
+        let packet = |s: &str| s.parse::<Packet>().unwrap();
+        assert_eq!(
+            packet("[1,[],[10,[2]]]"),
+            Packet::List(vec![
+                Packet::Int(1),
+                Packet::List(vec![]),
+                Packet::List(vec![Packet::Int(10), Packet::List(vec![Packet::Int(2)])]),
+            ])
+        );
+        assert_eq!(packet("[1,[],[10,[2]]]").to_string(), "[1,[],[10,[2]]]");
+
+        assert!(packet("[[1],[2,3,4]]") < packet("[[1],4]"));
+        assert!(packet("[9]") > packet("[[8,7,6]]"));
+        assert!(packet("[[[]]]") > packet("[[]]"));
+        assert_eq!(packet("[[2]]"), packet("[2]"));
+        assert_ne!(packet("[[2]]"), packet("[2,2]"));
+
+        assert_eq!("[1,2".parse::<Packet>(), Err(PacketError::UnexpectedEnd));
+        assert_eq!(
+            "[1,,2]".parse::<Packet>(),
+            Err(PacketError::UnexpectedCharacter {
+                position: 3,
+                character: ','
+            })
+        );
+        assert_eq!(
+            "[1]]".parse::<Packet>(),
+            Err(PacketError::TrailingInput { position: 3 })
+        );
+        assert_eq!(
+            "[99999999999]".parse::<Packet>(),
+            Err(PacketError::NumberTooLarge { position: 1 })
+        );
+    }
+
+    #[test]
+    fn test_write_sorted_packets() {
+        let input_path = get_test_input_path(file!());
+        let output_file = std::env::temp_dir().join("d13_sorted.txt");
+        let options = Options {
+            strategy: DecoderStrategy::RankCount,
+            sorted_output: Some(output_file.to_str().unwrap().to_string()),
+        };
+        run2(input_path.to_str().unwrap(), &options);
+
+        let sorted = std::fs::read_to_string(&output_file).unwrap();
+        let lines: Vec<&str> = sorted.lines().collect();
+        assert_eq!(lines.len(), 18);
+        assert_eq!(lines[0], "1: []");
+        assert_eq!(lines[9], "10: [[2]]");
+        assert_eq!(lines[13], "14: [[6]]");
+        assert_eq!(lines[17], "18: [9]");
     }
 }
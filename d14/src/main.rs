@@ -2,14 +2,54 @@
 
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
 
 use utils::get_input_path;
 
+const SAND_SOURCE_X: i64 = 500;
+
 struct Point {
     x: i64,
     y: i64,
 }
 
+struct Cave {
+    grid: Vec<Vec<char>>,
+    // World x coordinate of grid column 0
+    offset_x: i64,
+}
+
+enum SandStep {
+    Moved(usize, usize),
+    Rested,
+    Abyss,
+}
+
+struct ReplayOptions {
+    frames_per_second: u32,
+    viewport_width: usize,
+    viewport_height: usize,
+    // Only every n-th move of a grain is rendered
+    frame_stride: usize,
+    render_to_terminal: bool,
+    frame_dump_dir: Option<String>,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            frames_per_second: 30,
+            viewport_width: 80,
+            viewport_height: 30,
+            frame_stride: 1,
+            render_to_terminal: true,
+            frame_dump_dir: None,
+        }
+    }
+}
+
 #[allow(dead_code)]
 fn print_grid_to_file(grid: &Vec<Vec<char>>, path: &str) {
     let mut file = fs::File::create(path).unwrap();
@@ -21,6 +61,267 @@ fn print_grid_to_file(grid: &Vec<Vec<char>>, path: &str) {
     }
 }
 
+fn parse_rock_formation(input_file: &str) -> Vec<Vec<Point>> {
+    let file = File::open(input_file).unwrap();
+    let reader = BufReader::new(file);
+
+    let mut rock_formation: Vec<Vec<Point>> = Vec::new();
+    for line in reader.lines() {
+        let line = line.unwrap().trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        let mut rock_line = Vec::new();
+        for point in line.split(" -> ") {
+            let mut xy = point.split(',');
+            let x = xy.next().unwrap().trim().parse().unwrap();
+            let y = xy.next().unwrap().trim().parse().unwrap();
+            rock_line.push(Point { x, y });
+        }
+        rock_formation.push(rock_line);
+    }
+    rock_formation
+}
+
+/// Builds a cave just wide enough for the sand. Without a floor the grid
+/// ends at the lowest rock, everything leaving it falls into the abyss.
+fn create_cave(rock_formation: &[Vec<Point>], with_floor: bool) -> Cave {
+    let points = rock_formation.iter().flatten();
+    let max_y = points.clone().map(|p| p.y).max().unwrap();
+    let (sand_min_x, sand_max_x) = if with_floor {
+        // The sand pile is a triangle, it can never spread wider than its height
+        (SAND_SOURCE_X - max_y - 3, SAND_SOURCE_X + max_y + 3)
+    } else {
+        (SAND_SOURCE_X, SAND_SOURCE_X)
+    };
+    let (min_x, max_x) = (
+        points.clone().map(|p| p.x).min().unwrap().min(sand_min_x),
+        points.map(|p| p.x).max().unwrap().max(sand_max_x),
+    );
+    let height = if with_floor { max_y + 3 } else { max_y + 1 };
+
+    let mut grid = vec![vec!['.'; (max_x - min_x + 1) as usize]; height as usize];
+    if with_floor {
+        grid.last_mut().unwrap().fill('#');
+    }
+
+    for rock_line in rock_formation {
+        for [point1, point2] in rock_line.array_windows() {
+            for y in point1.y.min(point2.y)..=point1.y.max(point2.y) {
+                for x in point1.x.min(point2.x)..=point1.x.max(point2.x) {
+                    grid[y as usize][(x - min_x) as usize] = '#';
+                }
+            }
+        }
+    }
+
+    Cave {
+        grid,
+        offset_x: min_x,
+    }
+}
+
+fn step_sand(cave: &mut Cave, x: usize, y: usize) -> SandStep {
+    let next_y = y + 1;
+    if next_y >= cave.grid.len() {
+        return SandStep::Abyss;
+    }
+
+    for next_x in [Some(x), x.checked_sub(1), Some(x + 1)] {
+        let Some(next_x) = next_x.filter(|next_x| *next_x < cave.grid[next_y].len()) else {
+            return SandStep::Abyss;
+        };
+        if cave.grid[next_y][next_x] == '.' {
+            return SandStep::Moved(next_x, next_y);
+        }
+    }
+
+    cave.grid[y][x] = 'o';
+    SandStep::Rested
+}
+
+/// Renders the part of the cave around the falling grain, `~` marks the grain itself.
+fn render_frame(
+    cave: &Cave,
+    sand: (usize, usize),
+    options: &ReplayOptions,
+    header: &str,
+) -> String {
+    let width = cave.grid[0].len();
+    let height = cave.grid.len();
+    let viewport_width = options.viewport_width.min(width);
+    let viewport_height = options.viewport_height.min(height);
+    let left = sand
+        .0
+        .saturating_sub(viewport_width / 2)
+        .min(width - viewport_width);
+    let top = sand
+        .1
+        .saturating_sub(viewport_height / 2)
+        .min(height - viewport_height);
+
+    let source_x = (SAND_SOURCE_X - cave.offset_x) as usize;
+    let mut frame = String::new();
+    frame.push_str(header);
+    frame.push('\n');
+    for y in top..top + viewport_height {
+        for x in left..left + viewport_width {
+            let c = if (x, y) == sand {
+                '~'
+            } else if (x, y) == (source_x, 0) && cave.grid[y][x] == '.' {
+                '+'
+            } else {
+                cave.grid[y][x]
+            };
+            frame.push(c);
+        }
+        frame.push('\n');
+    }
+    frame
+}
+
+fn show_frame(frame: &str, frame_number: usize, options: &ReplayOptions) {
+    if options.render_to_terminal {
+        // Clear the screen and move the cursor to the top left corner
+        print!("\x1b[2J\x1b[H{}", frame);
+        std::io::stdout()
+            .flush()
+            .expect("Failed flushing the frame");
+        if options.frames_per_second > 0 {
+            thread::sleep(Duration::from_secs_f64(
+                1.0 / options.frames_per_second as f64,
+            ));
+        }
+    }
+    if let Some(dir) = &options.frame_dump_dir {
+        let path = Path::new(dir).join(format!("frame_{:06}.txt", frame_number));
+        fs::write(path, frame).expect("Failed writing the frame");
+    }
+}
+
+/// Replays the simulation move by move and returns the amount of resting sand.
+fn replay(input_file: &str, with_floor: bool, options: &ReplayOptions) -> usize {
+    let rock_formation = parse_rock_formation(input_file);
+    let mut cave = create_cave(&rock_formation, with_floor);
+    if let Some(dir) = &options.frame_dump_dir {
+        fs::create_dir_all(dir).expect("Failed creating the frame directory");
+    }
+
+    let source = ((SAND_SOURCE_X - cave.offset_x) as usize, 0);
+    let stride = options.frame_stride.max(1);
+    let mut sand_drops: usize = 0;
+    let mut moves: usize = 0;
+    let mut frame_number: usize = 0;
+
+    'grains: while cave.grid[source.1][source.0] == '.' {
+        let mut sand = source;
+        loop {
+            let step = step_sand(&mut cave, sand.0, sand.1);
+            if moves.is_multiple_of(stride) {
+                let header = format!(
+                    "Grain {} at ({}, {})",
+                    sand_drops + 1,
+                    sand.0 as i64 + cave.offset_x,
+                    sand.1
+                );
+                show_frame(
+                    &render_frame(&cave, sand, options, &header),
+                    frame_number,
+                    options,
+                );
+                frame_number += 1;
+            }
+            moves += 1;
+
+            match step {
+                SandStep::Moved(x, y) => sand = (x, y),
+                SandStep::Rested => break,
+                SandStep::Abyss => break 'grains,
+            }
+        }
+        sand_drops += 1;
+    }
+
+    sand_drops
+}
+
+/// Every argument configures the replay, so any of them turns it on.
+fn replay_options_from_args() -> Option<ReplayOptions> {
+    let mut args = std::env::args().skip(1).peekable();
+    args.peek()?;
+
+    let mut options = ReplayOptions::default();
+    for arg in args {
+        let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+        match key {
+            "--replay" => {}
+            "--fps" => options.frames_per_second = value.parse().expect("Invalid --fps"),
+            "--viewport" => {
+                let (width, height) = value.split_once('x').expect("Expected --viewport=WxH");
+                options.viewport_width = width.parse().expect("Invalid viewport width");
+                options.viewport_height = height.parse().expect("Invalid viewport height");
+            }
+            "--stride" => options.frame_stride = value.parse().expect("Invalid --stride"),
+            "--dump-frames" => options.frame_dump_dir = Some(value.to_string()),
+            "--no-terminal" => options.render_to_terminal = false,
+            _ => panic!("Unknown argument {}", arg),
+        }
+    }
+    Some(options)
+}
+
+/// Part 1 without restarting every grain at the source. The path of the
+/// previous grain is kept and the next grain continues from the spot right
+/// above where the last one came to rest.
+fn count_sand_backtracking(input_file: &str, with_floor: bool) -> usize {
+    let rock_formation = parse_rock_formation(input_file);
+    let mut cave = create_cave(&rock_formation, with_floor);
+
+    let mut sand_drops: usize = 0;
+    let mut path = vec![((SAND_SOURCE_X - cave.offset_x) as usize, 0)];
+    while let Some(&(x, y)) = path.last() {
+        match step_sand(&mut cave, x, y) {
+            SandStep::Moved(next_x, next_y) => path.push((next_x, next_y)),
+            SandStep::Rested => {
+                sand_drops += 1;
+                path.pop();
+            }
+            SandStep::Abyss => break,
+        }
+    }
+
+    sand_drops
+}
+
+/// Part 2 without simulating single grains. With a floor every cell that can
+/// be reached from the source ends up filled, so the pile is computed row by
+/// row: a cell is filled if one of the three cells above it is filled and it
+/// is not rock.
+fn count_sand_reachable(input_file: &str) -> usize {
+    let rock_formation = parse_rock_formation(input_file);
+    let cave = create_cave(&rock_formation, true);
+    let width = cave.grid[0].len();
+    let floor_y = cave.grid.len() - 1;
+
+    let mut row = vec![false; width];
+    row[(SAND_SOURCE_X - cave.offset_x) as usize] = true;
+    let mut sand_drops: usize = 1;
+
+    for y in 1..floor_y {
+        let next_row: Vec<bool> = (0..width)
+            .map(|x| {
+                cave.grid[y][x] != '#'
+                    && (row[x] || (x > 0 && row[x - 1]) || (x + 1 < width && row[x + 1]))
+            })
+            .collect();
+        sand_drops += next_row.iter().filter(|filled| **filled).count();
+        row = next_row;
+    }
+
+    sand_drops
+}
+
+#[cfg(test)]
 fn is_abyss(next_x: usize, next_y: usize, min_x: i64, min_y: i64, max_x: i64, max_y: i64) -> bool {
     next_y < min_y as usize
         || next_y >= max_y as usize
@@ -28,7 +329,9 @@ fn is_abyss(next_x: usize, next_y: usize, min_x: i64, min_y: i64, max_x: i64, ma
         || next_x >= (max_x - min_x) as usize
 }
 
-fn run(input_file: &str) {
+// Reference simulation, the faster solvers are checked against it in the tests
+#[cfg(test)]
+fn run(input_file: &str) -> usize {
     // Preamble
     const SAND_START: i64 = 500;
     let mut min_x: i64 = i64::MAX;
@@ -185,9 +488,11 @@ fn run(input_file: &str) {
 
     // print_grid_to_file(&grid, "target/output2.txt");
     println!("There are {} sanddrops falling until the abyss is reached", sand_drops);
+    sand_drops
 }
 
-fn run2(input_file: &str) {
+#[cfg(test)]
+fn run2(input_file: &str) -> usize {
     // Preamble
     const SAND_START: i64 = 500;
     let mut min_x: i64 = i64::MAX;
@@ -356,6 +661,7 @@ fn run2(input_file: &str) {
 
     // print_grid_to_file(&grid, "target/output2.txt");
     println!("There are {} sanddrops falling until the top is reached", sand_drops);
+    sand_drops
 }
 
 fn main() {
@@ -364,16 +670,43 @@ fn main() {
 
     println!("{:?}", input_file);
 
-    run(&input_file);
-    run2(&input_file);
+    if let Some(options) = replay_options_from_args() {
+        let sand_drops = replay(input_file, false, &options);
+        println!(
+            "There are {} sanddrops falling until the abyss is reached",
+            sand_drops
+        );
+        let sand_drops = replay(input_file, true, &options);
+        println!(
+            "There are {} sanddrops falling until the top is reached",
+            sand_drops
+        );
+        return;
+    }
+
+    let sand_drops = count_sand_backtracking(input_file, false);
+    println!(
+        "There are {} sanddrops falling until the abyss is reached",
+        sand_drops
+    );
+    let sand_drops = count_sand_reachable(input_file);
+    println!(
+        "There are {} sanddrops falling until the top is reached",
+        sand_drops
+    );
 }
 
 #[cfg(test)]
 mod main_test {
+    use utils::get_test_input_2_path;
     use utils::get_test_input_path;
 
+    use crate::count_sand_backtracking;
+    use crate::count_sand_reachable;
+    use crate::replay;
     use crate::run;
     use crate::run2;
+    use crate::ReplayOptions;
 
     #[test]
     fn test_input_part_1() {
@@ -386,4 +719,72 @@ mod main_test {
         let input_path = get_test_input_path(file!());
         run2(input_path.to_str().unwrap());
     }
+
+    #[test]
+    fn test_replay_dumps_frames() {
+        let input_path = get_test_input_path(file!());
+        let dump_frames = |stride: usize| {
+            let dump_dir = std::env::temp_dir().join(format!(
+                "d14_replay_frames_{}_{}",
+                std::process::id(),
+                stride
+            ));
+            let _ = std::fs::remove_dir_all(&dump_dir);
+            let options = ReplayOptions {
+                render_to_terminal: false,
+                frame_stride: stride,
+                frame_dump_dir: Some(dump_dir.to_string_lossy().to_string()),
+                ..Default::default()
+            };
+            assert_eq!(replay(input_path.to_str().unwrap(), false, &options), 24);
+            let frame_count = std::fs::read_dir(&dump_dir).unwrap().count();
+            let first_frame = std::fs::read_to_string(dump_dir.join("frame_000000.txt")).unwrap();
+            std::fs::remove_dir_all(&dump_dir).unwrap();
+            (frame_count, first_frame)
+        };
+
+        // One frame per move, the first grain starts on the source
+        let (moves, first_frame) = dump_frames(1);
+        let lines: Vec<&str> = first_frame.lines().collect();
+        assert_eq!(lines[0], "Grain 1 at (500, 0)");
+        assert_eq!(lines.len(), 1 + 10);
+        assert_eq!(lines[1], "......~...");
+        assert_eq!(lines[10], "#########.");
+        assert!(moves > 24);
+        assert_eq!(dump_frames(5).0, moves.div_ceil(5));
+
+        let options = ReplayOptions {
+            render_to_terminal: false,
+            ..Default::default()
+        };
+        assert_eq!(replay(input_path.to_str().unwrap(), true, &options), 93);
+    }
+
+    #[test]
+    fn test_floor_with_rocks_outside_the_pile() {
+        // A rock far left of the sand triangle still has to fit into the grid
+        let input_path = get_test_input_2_path(file!());
+        let options = ReplayOptions {
+            render_to_terminal: false,
+            ..Default::default()
+        };
+        assert_eq!(replay(input_path.to_str().unwrap(), false, &options), 24);
+        assert_eq!(replay(input_path.to_str().unwrap(), true, &options), 93);
+    }
+
+    #[test]
+    fn test_fast_solvers_match_simulation() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        assert_eq!(count_sand_backtracking(input_file, false), run(input_file));
+        assert_eq!(count_sand_backtracking(input_file, true), run2(input_file));
+        assert_eq!(count_sand_reachable(input_file), run2(input_file));
+
+        // Rocks outside of the sand triangle
+        let input_path = get_test_input_2_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        assert_eq!(count_sand_backtracking(input_file, false), run(input_file));
+        assert_eq!(count_sand_backtracking(input_file, true), run2(input_file));
+        assert_eq!(count_sand_reachable(input_file), run2(input_file));
+    }
 }
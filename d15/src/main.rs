@@ -1,10 +1,9 @@
-use std::cmp::max;
-use std::collections::HashSet;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use regex::Regex;
 use utils::get_input_path;
+use utils::interval_set::IntervalSet;
 
 struct Beacon {
     pub x: i64,
@@ -16,20 +15,42 @@ struct Sensor {
     pub y: i64,
 }
 
-#[derive(PartialEq, Eq, PartialOrd)]
-struct Interval {
-    pub lo: i64,
-    pub hi: i64,
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum SearchStrategy {
+    BoundaryLines,
+    RowScan,
 }
-impl Interval {
-    pub(crate) fn delta(&self) -> i64 {
-        self.hi - self.lo + 1
+
+struct Options {
+    row_to_check: Option<i64>,
+    search_limit: Option<i64>,
+    strategy: SearchStrategy,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            row_to_check: None,
+            search_limit: None,
+            strategy: SearchStrategy::BoundaryLines,
+        }
     }
 }
 
-impl Ord for Interval {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.lo.cmp(&other.lo)
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        for arg in std::env::args().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                "--row" => options.row_to_check = Some(value.parse().expect("Invalid --row")),
+                "--limit" => options.search_limit = Some(value.parse().expect("Invalid --limit")),
+                // The row scan is kept to cross check the boundary line search
+                "--row-scan" => options.strategy = SearchStrategy::RowScan,
+                _ => panic!("Unknown argument {}", arg),
+            }
+        }
+        options
     }
 }
 
@@ -69,14 +90,73 @@ impl SensorBeaconGroup {
     }
 }
 
-fn intersect(row_to_check: i64, lo: i64, hi: i64, x: i64, y: i64) -> bool {
-    if row_to_check == y {
-        return x >= lo && x <= hi;
+/// The example only uses coordinates up to a few dozen while the real input
+/// goes into the millions, so the size of the coordinates tells which search
+/// limit the puzzle asks for. The row to check is always half of it.
+fn detect_search_limit(max_x: i64, max_y: i64) -> i64 {
+    if max_x.max(max_y) <= 100 {
+        20
+    } else {
+        4000000
+    }
+}
+
+fn is_covered(sensor_beacons: &mut [SensorBeaconGroup], x: i64, y: i64) -> bool {
+    sensor_beacons.iter_mut().any(|sensor_beacon| {
+        (sensor_beacon.sensor.x - x).abs() + (sensor_beacon.sensor.y - y).abs()
+            <= sensor_beacon.sensor_beacon_distance()
+    })
+}
+
+/// The only uncovered position has to lie directly outside the range of the
+/// sensors around it, so it sits on the diagonal lines one step past each
+/// sensor radius. Only the crossings of those lines (and where they hit the
+/// border of the search area) need to be checked.
+fn find_gap_by_boundary_lines(
+    sensor_beacons: &mut [SensorBeaconGroup],
+    min_working: i64,
+    max_working: i64,
+) -> Option<(i64, i64)> {
+    // Lines x + y = c go up to the right, lines x - y = c go down to the right
+    let mut rising: Vec<i64> = Vec::new();
+    let mut falling: Vec<i64> = Vec::new();
+    for sensor_beacon in sensor_beacons.iter_mut() {
+        let radius = sensor_beacon.sensor_beacon_distance() + 1;
+        let sensor = &sensor_beacon.sensor;
+        rising.extend([sensor.x + sensor.y - radius, sensor.x + sensor.y + radius]);
+        falling.extend([sensor.x - sensor.y - radius, sensor.x - sensor.y + radius]);
+    }
+    rising.sort_unstable();
+    rising.dedup();
+    falling.sort_unstable();
+    falling.dedup();
+
+    let mut candidates: Vec<(i64, i64)> = Vec::new();
+    for a in &rising {
+        for b in &falling {
+            if (a + b) % 2 == 0 {
+                candidates.push(((a + b) / 2, (a - b) / 2));
+            }
+        }
+    }
+    for border in [min_working, max_working] {
+        for a in &rising {
+            candidates.extend([(border, a - border), (a - border, border)]);
+        }
+        for b in &falling {
+            candidates.extend([(border, border - b), (b + border, border)]);
+        }
+        candidates.extend([(border, min_working), (border, max_working)]);
     }
-    return false;
+
+    candidates.into_iter().find(|(x, y)| {
+        (min_working..=max_working).contains(x)
+            && (min_working..=max_working).contains(y)
+            && !is_covered(sensor_beacons, *x, *y)
+    })
 }
 
-fn run(input_file: &str) {
+fn run(input_file: &str, options: &Options) -> i64 {
     // Preamble
     let mut min_x: i64 = i64::MAX;
     let mut min_y: i64 = i64::MAX;
@@ -121,14 +201,12 @@ fn run(input_file: &str) {
     // let min_working_y: i64 = min_y - max_distance;
     // let max_working_x: i64 = max_x + max_distance;
     // let max_working_y: i64 = max_y + max_distance;
-    let row_to_check: i64 = if sensor_beacons.len() == 14 {
-        10
-    } else {
-        2000000
-    };
+    let row_to_check: i64 = options
+        .row_to_check
+        .unwrap_or_else(|| detect_search_limit(max_x, max_y) / 2);
 
     // Solve
-    let mut intervals: Vec<Interval> = Vec::new();
+    let mut covered = IntervalSet::new();
     for sensor_beacon in sensor_beacons.iter_mut() {
         let sensor_distance = sensor_beacon.distance_to_y(row_to_check);
 
@@ -141,56 +219,23 @@ fn run(input_file: &str) {
         let lo = sensor_beacon.sensor.x - singal_half_length;
         let hi = sensor_beacon.sensor.x + singal_half_length;
 
-        intervals.push(Interval { lo, hi });
+        covered.insert(lo, hi);
     }
 
-    intervals.sort_unstable();
-
-    let mut q: Vec<Interval> = Vec::new();
-    for interval in intervals {
-        let lo = interval.lo;
-        let hi = interval.hi;
-
-        if q.is_empty() {
-            q.push(interval);
-            continue;
-        }
-
-        let q_hi = q.last().unwrap().hi;
-
-        if lo > q_hi {
-            q.push(interval);
-            continue;
+    for sensor_beacon in sensor_beacons.iter() {
+        if sensor_beacon.beacon.y == row_to_check {
+            covered.remove(sensor_beacon.beacon.x, sensor_beacon.beacon.x);
         }
-
-        q.last_mut().unwrap().hi = max(hi, q_hi);
     }
 
     // Result
-    let mut result = 0;
-    let mut skip_x: HashSet<i64> = HashSet::new();
-    for i in q {
-        result += i.delta();
-        for sensor_beacon in sensor_beacons.iter() {
-            if !skip_x.contains(&sensor_beacon.beacon.x)
-                && intersect(
-                    row_to_check,
-                    i.lo,
-                    i.hi,
-                    sensor_beacon.beacon.x,
-                    sensor_beacon.beacon.y,
-                )
-            {
-                skip_x.insert(sensor_beacon.beacon.x);
-                result -= 1;
-            }
-        }
-    }
+    let result = covered.len();
 
     println!("Result is {}", result);
+    result
 }
 
-fn run2(input_file: &str) {
+fn run2(input_file: &str, options: &Options) -> i64 {
     // Preamble
     let mut result = i64::MIN;
     let mut min_x: i64 = i64::MAX;
@@ -233,11 +278,9 @@ fn run2(input_file: &str) {
 
     // Prepare
 
-    let check_range: i64 = if sensor_beacons.len() == 14 {
-        20
-    } else {
-        4000000
-    };
+    let check_range: i64 = options
+        .search_limit
+        .unwrap_or_else(|| detect_search_limit(max_x, max_y));
 
     let min_working_x: i64 = 0;
     let min_working_y: i64 = 0;
@@ -245,60 +288,51 @@ fn run2(input_file: &str) {
     let max_working_y: i64 = check_range;
 
     // Solve
-    for y in min_working_y..max_working_y + 1 {
-        let mut intervals: Vec<Interval> = Vec::new();
-        for sensor_beacon in sensor_beacons.iter_mut() {
-            let sensor_distance = sensor_beacon.distance_to_y(y);
-
-            if sensor_distance > sensor_beacon.sensor_beacon_distance() {
-                continue;
+    match options.strategy {
+        SearchStrategy::BoundaryLines => {
+            if let Some((x, y)) =
+                find_gap_by_boundary_lines(&mut sensor_beacons, min_working_x, max_working_x)
+            {
+                result = (x * 4000000) + y;
             }
-
-            let singal_half_length = sensor_beacon.sensor_beacon_distance() - sensor_distance;
-
-            let lo = (sensor_beacon.sensor.x - singal_half_length).max(min_working_x);
-            let hi = (sensor_beacon.sensor.x + singal_half_length).min(max_working_x);
-
-            intervals.push(Interval { lo, hi });
         }
-
-        intervals.sort_unstable();
-
-        let mut q: Vec<Interval> = Vec::new();
-        for interval in intervals {
-            let lo = interval.lo;
-            let hi = interval.hi;
-
-            if q.is_empty() {
-                q.push(interval);
-                continue;
-            }
-
-            let q_hi = q.last().unwrap().hi;
-
-            if lo > q_hi {
-                q.push(interval);
-                continue;
+        SearchStrategy::RowScan => {
+            for y in min_working_y..max_working_y + 1 {
+                let mut covered = IntervalSet::new();
+                for sensor_beacon in sensor_beacons.iter_mut() {
+                    let sensor_distance = sensor_beacon.distance_to_y(y);
+
+                    if sensor_distance > sensor_beacon.sensor_beacon_distance() {
+                        continue;
+                    }
+
+                    let singal_half_length =
+                        sensor_beacon.sensor_beacon_distance() - sensor_distance;
+
+                    let lo = (sensor_beacon.sensor.x - singal_half_length).max(min_working_x);
+                    let hi = (sensor_beacon.sensor.x + singal_half_length).min(max_working_x);
+                    if lo > hi {
+                        continue;
+                    }
+
+                    covered.insert(lo, hi);
+                }
+
+                if let Some(gap) = covered.gaps(min_working_x, max_working_x).iter().next() {
+                    result = (gap.lo * 4000000) + y;
+                    break;
+                }
             }
-
-            q.last_mut().unwrap().hi = max(hi, q_hi);
-        }
-
-        if q.len() == 1 {
-            continue;
-        } else {
-           result = ((q.first().unwrap().hi+1) * 4000000) +  y;
-           break;
         }
     }
-    
+
     // Result
     if result == i64::MIN {
         println!("Failed to find anything");
     } else {
         println!("Found signal with frequenze {}", result);
     }
-    
+    result
 }
 
 fn main() {
@@ -307,8 +341,9 @@ fn main() {
 
     println!("{:?}", input_file);
 
-    run(&input_file);
-    run2(&input_file);
+    let options = Options::from_args();
+    run(&input_file, &options);
+    run2(&input_file, &options);
 }
 
 #[cfg(test)]
@@ -317,16 +352,47 @@ mod main_test {
 
     use crate::run;
     use crate::run2;
+    use crate::Options;
+    use crate::SearchStrategy;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        run(input_path.to_str().unwrap(), &Options::default());
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        run2(input_path.to_str().unwrap(), &Options::default());
+    }
+
+    #[test]
+    fn test_boundary_lines_match_row_scan() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        let row_scan = Options {
+            strategy: SearchStrategy::RowScan,
+            ..Default::default()
+        };
+        assert_eq!(
+            run2(input_file, &Options::default()),
+            run2(input_file, &row_scan)
+        );
+    }
+
+    #[test]
+    fn test_detected_bounds_match_example() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        let example = Options {
+            row_to_check: Some(10),
+            search_limit: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(run(input_file, &Options::default()), 26);
+        assert_eq!(run(input_file, &example), 26);
+        assert_eq!(run2(input_file, &Options::default()), 56000011);
+        assert_eq!(run2(input_file, &example), 56000011);
     }
 }
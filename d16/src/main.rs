@@ -1,7 +1,9 @@
 use std::cmp::max;
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::fs::File;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
+use std::path::Path;
 
 use regex::Regex;
 use utils::get_input_path;
@@ -12,340 +14,507 @@ struct Valve {
     pub to_valves: Vec<String>,
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
-struct CacheObject {
-    pub time: i64,
-    pub valve: String,
-    pub bitmask: u64,
+/// The memo is dense over the subsets of flowing valves, with 20 of them it
+/// already takes about 2.7 GB for 30 minutes.
+const MAX_FLOWING_VALVES: usize = 20;
+
+/// Valves interned to indices. The valves with a flow rate come first, their
+/// index is also their bit in the masks, the start valve follows them.
+struct ValveGraph {
+    pub names: Vec<String>,
+    pub rates: Vec<u64>,
+    pub start: usize,
+    pub flowing: usize,
+    // Shortest distance between every pair of valves
+    pub distance: Vec<Vec<i64>>,
 }
 
-struct DistanceObject {
-    pub distance: i64,
-    pub valve_name: String,
+impl ValveGraph {
+    fn new(valve_map: &HashMap<String, Valve>, valve_list: &[String], start_valve: &str) -> Self {
+        let mut order: Vec<&String> = valve_list
+            .iter()
+            .filter(|name| valve_map[*name].rate > 0)
+            .collect();
+        assert!(
+            valve_map.contains_key(start_valve),
+            "Unknown start valve {}",
+            start_valve
+        );
+        let flowing = order.len();
+        assert!(
+            flowing <= MAX_FLOWING_VALVES,
+            "Only up to {} valves with a flow rate are supported, the memo holds \
+             2^n entries per minute and valve and would not fit into memory",
+            MAX_FLOWING_VALVES
+        );
+        order.extend(valve_list.iter().filter(|name| valve_map[*name].rate == 0));
+        let index: HashMap<&String, usize> = order
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (*name, i))
+            .collect();
+
+        // Floyd–Warshall
+        let unreachable = i64::MAX / 2;
+        let mut distance = vec![vec![unreachable; order.len()]; order.len()];
+        for (i, name) in order.iter().enumerate() {
+            distance[i][i] = 0;
+            for to_valve in &valve_map[*name].to_valves {
+                distance[i][index[to_valve]] = 1;
+            }
+        }
+        for k in 0..order.len() {
+            for i in 0..order.len() {
+                for j in 0..order.len() {
+                    let through_k = distance[i][k] + distance[k][j];
+                    if through_k < distance[i][j] {
+                        distance[i][j] = through_k;
+                    }
+                }
+            }
+        }
+
+        ValveGraph {
+            names: order.iter().map(|name| name.to_string()).collect(),
+            rates: order.iter().map(|name| valve_map[*name].rate).collect(),
+            start: index[&start_valve.to_string()],
+            flowing,
+            distance,
+        }
+    }
 }
 
-fn dfs(
-    time: i64,
-    valve: String,
-    bitmask: u64,
-    cache: &mut HashMap<CacheObject, u64>,
-    valve_map: &HashMap<String, Valve>,
-    idx_map: &HashMap<String, u64>,
-    distance: &HashMap<String, HashMap<String, i64>>,
-) -> u64 {
-    let cache_object = CacheObject {
-        time,
-        valve: valve.clone(),
-        bitmask,
-    };
+/// Memo of the best release for every (time, valve, bitmask), `u32::MAX`
+/// marks states that were not computed yet.
+struct Cache {
+    values: Vec<u32>,
+    valves: usize,
+    masks: usize,
+}
+
+impl Cache {
+    fn new(graph: &ValveGraph, time: i64) -> Self {
+        // One extra slot for the start valve
+        let valves = graph.flowing + 1;
+        let masks = 1 << graph.flowing;
+        Cache {
+            values: vec![u32::MAX; (time as usize + 1) * valves * masks],
+            valves,
+            masks,
+        }
+    }
 
-    // println!("Current Setting: {:?}", cache_object);
-    let current_cache = cache.get(&cache_object);
+    fn index(&self, graph: &ValveGraph, time: i64, valve: usize, bitmask: u64) -> usize {
+        let valve = if valve == graph.start {
+            graph.flowing
+        } else {
+            valve
+        };
+        (time as usize * self.valves + valve) * self.masks + bitmask as usize
+    }
+}
 
-    if current_cache.is_some() {
-        return current_cache.unwrap().clone();
+struct ValveOpening {
+    pub minute: i64,
+    pub valve: String,
+    pub actor: usize,
+    pub released: u64,
+}
+
+fn actor_opens(actor: usize) -> String {
+    match actor {
+        0 => String::from("You open"),
+        1 => String::from("The elephant opens"),
+        _ => format!("Elephant {} opens", actor),
     }
+}
 
-    let mut max_val: u64 = 0;
-    let current_valve = valve_map.get(&valve).unwrap();
+fn dfs(time: i64, valve: usize, bitmask: u64, cache: &mut Cache, graph: &ValveGraph) -> u64 {
+    let cache_index = cache.index(graph, time, valve, bitmask);
+    if cache.values[cache_index] != u32::MAX {
+        return cache.values[cache_index] as u64;
+    }
 
-    for neighbor in distance.get(&current_valve.name).unwrap().keys() {
-        let idx = idx_map.get(neighbor).unwrap();
-        let bit: u64 = 1 << idx;
+    let mut max_val: u64 = 0;
+    for neighbor in 0..graph.flowing {
+        let bit: u64 = 1 << neighbor;
         if (bitmask & bit) != 0 {
             continue;
         }
-        let neighbor_valve = valve_map.get(neighbor).unwrap();
-        let neighbor_distance = distance.get(&valve).unwrap().get(neighbor).unwrap();
-        let rem_time = time - neighbor_distance - 1;
+        let rem_time = time - graph.distance[valve][neighbor] - 1;
         if rem_time <= 0 {
             continue;
         }
 
-        let new_bitmask = bitmask | bit;
-        let new_max_value = dfs(
-            rem_time,
-            neighbor.clone(),
-            new_bitmask,
-            cache,
-            valve_map,
-            idx_map,
-            distance,
-        ) + rem_time as u64 * neighbor_valve.rate;
+        let new_max_value = dfs(rem_time, neighbor, bitmask | bit, cache, graph)
+            + rem_time as u64 * graph.rates[neighbor];
         max_val = max(max_val, new_max_value)
     }
-    cache.insert(cache_object, max_val);
-    return max_val;
+    cache.values[cache_index] = max_val.try_into().unwrap();
+    max_val
 }
 
-fn run(input_file: &str) {
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-
-    let mut valve_map: HashMap<String, Valve> = HashMap::new();
-    let mut valve_list: Vec<String> = Vec::new();
-    let mut cache: HashMap<CacheObject, u64> = HashMap::new();
-    let mut distance: HashMap<String, HashMap<String, i64>> = HashMap::new();
-
-    let start_valve = String::from("AA");
-    let time = 30;
-
-    // Parse
-    for (_index, line) in reader.lines().enumerate() {
-        let trimmed_line = line.unwrap().trim().to_string();
-
-        if trimmed_line.is_empty() {
-            continue;
-        }
-        let rgx =
-            Regex::new(r#"Valve (\w\w) has flow rate=(\d\d?); tunnels? leads? to valves? (.+)"#)
-                .unwrap();
-
-        let captures = rgx.captures(&trimmed_line).unwrap();
-        let valve: &str = &captures[1];
-        let rate: &u64 = &captures[2].parse().unwrap();
-        let lead_to: &str = &captures[3];
-
-        let splits = lead_to.split(", ");
-        let mut to_valves: Vec<String> = Vec::new();
+/// Walks the filled cache from the given state and follows the valve that
+/// produced the cached maximum on every step.
+fn reconstruct_route(
+    total_time: i64,
+    actor: usize,
+    mut valve: usize,
+    mut bitmask: u64,
+    cache: &mut Cache,
+    graph: &ValveGraph,
+) -> Vec<ValveOpening> {
+    let mut route = Vec::new();
+    let mut time = total_time;
+    let mut remaining = dfs(time, valve, bitmask, cache, graph);
+
+    while remaining > 0 {
+        let mut next: Option<(usize, i64, u64)> = None;
+        for neighbor in 0..graph.flowing {
+            let bit: u64 = 1 << neighbor;
+            let rem_time = time - graph.distance[valve][neighbor] - 1;
+            if (bitmask & bit) != 0 || rem_time <= 0 {
+                continue;
+            }
 
-        for s in splits {
-            to_valves.push(s.to_string());
+            let released = rem_time as u64 * graph.rates[neighbor];
+            if dfs(rem_time, neighbor, bitmask | bit, cache, graph) + released == remaining {
+                next = Some((neighbor, rem_time, released));
+                break;
+            }
         }
 
-        let valve = Valve {
-            name: valve.to_string(),
-            rate: rate.clone(),
-            to_valves: to_valves,
-        };
-
-        valve_list.push(valve.name.clone());
-        valve_map.insert(valve.name.clone(), valve);
+        let (neighbor, rem_time, released) = next.expect("Cache does not match the route");
+        route.push(ValveOpening {
+            minute: total_time - rem_time,
+            valve: graph.names[neighbor].clone(),
+            actor,
+            released,
+        });
+        remaining -= released;
+        bitmask |= 1 << neighbor;
+        time = rem_time;
+        valve = neighbor;
     }
 
-    // Prepare
-    // Distance
-    let mut non_empty: Vec<String> = Vec::new();
-    for key in &valve_list {
-        if key != &start_valve && valve_map.get(key).unwrap().rate == 0 {
-            continue;
-        }
-
-        if key != &start_valve {
-            non_empty.push(key.clone());
-        }
-
-        distance.insert(key.clone(), HashMap::from([(start_valve.clone(), 0)]));
-        let mut visited: HashSet<String> = HashSet::from([key.clone()]);
+    route
+}
 
-        let mut queue: VecDeque<DistanceObject> = VecDeque::from([DistanceObject {
-            distance: 0,
-            valve_name: key.clone(),
-        }]);
+fn print_schedule(route: &mut [ValveOpening], total_time: i64) {
+    route.sort_by_key(|opening| (opening.minute, opening.actor));
+    println!("Valve opening schedule:");
+    for opening in route.iter() {
+        println!(
+            "  Minute {:>2}: {} valve {}, releasing {} over the remaining {} minutes",
+            opening.minute,
+            actor_opens(opening.actor),
+            opening.valve,
+            opening.released,
+            total_time - opening.minute
+        );
+    }
+}
 
-        while !queue.is_empty() {
-            let distance_object = queue.pop_front().unwrap();
-            let current_distance = distance_object.distance;
-            let valve_name = distance_object.valve_name;
+struct Options {
+    start_valve: String,
+    agents: usize,
+    time: i64,
+    // Directory for the Graphviz files of the tunnels and the chosen route
+    dot_dir: Option<String>,
+}
 
-            let current_valve = valve_map.get(&valve_name).unwrap();
+impl Options {
+    fn from_args() -> Option<Self> {
+        let mut args = std::env::args().skip(1).peekable();
+        args.peek()?;
 
-            for neighbor in &current_valve.to_valves {
-                if visited.contains(neighbor) {
-                    continue;
-                }
-                visited.insert(neighbor.clone());
-                let neighbor_valve = valve_map.get(neighbor).unwrap();
-                if neighbor_valve.rate != 0 {
-                    distance
-                        .get_mut(key)
-                        .unwrap()
-                        .insert(neighbor.clone(), current_distance + 1);
-                }
-                queue.push_back(DistanceObject {
-                    distance: current_distance + 1,
-                    valve_name: neighbor.clone(),
-                })
+        let mut options = Options {
+            start_valve: String::from("AA"),
+            agents: 1,
+            time: 30,
+            dot_dir: None,
+        };
+        for arg in args {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                "--start" => options.start_valve = value.to_string(),
+                "--agents" => options.agents = value.parse().expect("Invalid --agents"),
+                "--time" => options.time = value.parse().expect("Invalid --time"),
+                "--dot" => options.dot_dir = Some(value.to_string()),
+                _ => panic!("Unknown argument {}", arg),
             }
         }
-
-        distance.get_mut(key).unwrap().remove(key);
-        if key != &start_valve {
-            distance.get_mut(key).unwrap().remove(&start_valve);
-        }
+        Some(options)
     }
-
-    // Index
-    let mut idx_map: HashMap<String, u64> = HashMap::new();
-    for (i, value) in non_empty.iter().enumerate() {
-        idx_map.insert(value.clone(), i.try_into().unwrap());
-    }
-
-    // Solve
-    let result = dfs(
-        time,
-        start_valve.to_string(),
-        0,
-        &mut cache,
-        &valve_map,
-        &idx_map,
-        &distance,
-    );
-
-    println!("The end result is: {}", result);
 }
 
-fn run2(input_file: &str) {
+fn parse_valves(input_file: &str) -> (HashMap<String, Valve>, Vec<String>) {
     let file = File::open(input_file).unwrap();
     let reader = BufReader::new(file);
 
     let mut valve_map: HashMap<String, Valve> = HashMap::new();
     let mut valve_list: Vec<String> = Vec::new();
-    let mut cache: HashMap<CacheObject, u64> = HashMap::new();
-    let mut distance: HashMap<String, HashMap<String, i64>> = HashMap::new();
-
-    let start_valve = String::from("AA");
-    let time = 26;
+    let rgx = Regex::new(r#"Valve (\w\w) has flow rate=(\d\d?); tunnels? leads? to valves? (.+)"#)
+        .unwrap();
 
-    // Parse
-    for (_index, line) in reader.lines().enumerate() {
+    for line in reader.lines() {
         let trimmed_line = line.unwrap().trim().to_string();
 
         if trimmed_line.is_empty() {
             continue;
         }
-        let rgx =
-            Regex::new(r#"Valve (\w\w) has flow rate=(\d\d?); tunnels? leads? to valves? (.+)"#)
-                .unwrap();
 
         let captures = rgx.captures(&trimmed_line).unwrap();
-        let valve: &str = &captures[1];
-        let rate: &u64 = &captures[2].parse().unwrap();
-        let lead_to: &str = &captures[3];
-
-        let splits = lead_to.split(", ");
-        let mut to_valves: Vec<String> = Vec::new();
-
-        for s in splits {
-            to_valves.push(s.to_string());
-        }
-
         let valve = Valve {
-            name: valve.to_string(),
-            rate: rate.clone(),
-            to_valves: to_valves,
+            name: captures[1].to_string(),
+            rate: captures[2].parse().unwrap(),
+            to_valves: captures[3].split(", ").map(|s| s.to_string()).collect(),
         };
 
         valve_list.push(valve.name.clone());
         valve_map.insert(valve.name.clone(), valve);
     }
 
-    // Prepare
-    // Distance
-    let mut non_empty: Vec<String> = Vec::new();
-    for key in &valve_list {
-        if key != &start_valve && valve_map.get(key).unwrap().rate == 0 {
-            continue;
-        }
+    (valve_map, valve_list)
+}
 
-        if key != &start_valve {
-            non_empty.push(key.clone());
+/// Splits the valves between the agents. Every agent works on its own set of
+/// valves, so the best single agent result for each set is computed once and
+/// the sets are then combined one agent at a time over disjoint subsets.
+fn solve(graph: &ValveGraph, options: &Options) -> (u64, Vec<ValveOpening>) {
+    assert!(options.agents > 0, "At least one agent is needed");
+
+    let mut cache = Cache::new(graph, options.time);
+
+    // Best result of a single agent that may only open the valves in the mask
+    let full: u64 = (1 << graph.flowing) - 1;
+    let best_per_mask: Vec<u64> = (0..=full)
+        .map(|mask| dfs(options.time, graph.start, full ^ mask, &mut cache, graph))
+        .collect();
+
+    // Best result of k agents for every mask and the share of the last agent
+    let mut best = best_per_mask.clone();
+    let mut choices: Vec<Vec<u64>> = Vec::new();
+    for _ in 1..options.agents {
+        let mut next_best = vec![0; best.len()];
+        let mut choice = vec![0; best.len()];
+        for mask in 0..=full {
+            let mut share = mask;
+            loop {
+                let released = best_per_mask[share as usize] + best[(mask ^ share) as usize];
+                if released > next_best[mask as usize] {
+                    next_best[mask as usize] = released;
+                    choice[mask as usize] = share;
+                }
+                if share == 0 {
+                    break;
+                }
+                share = (share - 1) & mask;
+            }
         }
+        best = next_best;
+        choices.push(choice);
+    }
 
-        distance.insert(key.clone(), HashMap::from([(start_valve.clone(), 0)]));
-        let mut visited: HashSet<String> = HashSet::from([key.clone()]);
-
-        let mut queue: VecDeque<DistanceObject> = VecDeque::from([DistanceObject {
-            distance: 0,
-            valve_name: key.clone(),
-        }]);
+    // Route
+    let mut route = Vec::new();
+    let mut mask = full;
+    for actor in 0..options.agents {
+        let share = if actor + 1 < options.agents {
+            choices[options.agents - 2 - actor][mask as usize]
+        } else {
+            mask
+        };
+        route.extend(reconstruct_route(
+            options.time,
+            actor,
+            graph.start,
+            full ^ share,
+            &mut cache,
+            graph,
+        ));
+        mask ^= share;
+    }
 
-        while !queue.is_empty() {
-            let distance_object = queue.pop_front().unwrap();
-            let current_distance = distance_object.distance;
-            let valve_name = distance_object.valve_name;
+    (best[full as usize], route)
+}
 
-            let current_valve = valve_map.get(&valve_name).unwrap();
+const ACTOR_COLORS: [&str; 4] = ["red", "blue", "darkgreen", "orange"];
 
-            for neighbor in &current_valve.to_valves {
-                if visited.contains(neighbor) {
-                    continue;
-                }
-                visited.insert(neighbor.clone());
-                let neighbor_valve = valve_map.get(neighbor).unwrap();
-                if neighbor_valve.rate != 0 {
-                    distance
-                        .get_mut(key)
-                        .unwrap()
-                        .insert(neighbor.clone(), current_distance + 1);
-                }
-                queue.push_back(DistanceObject {
-                    distance: current_distance + 1,
-                    valve_name: neighbor.clone(),
-                })
+/// Every tunnel of the cave, valves with a flow rate are filled and the
+/// opened valves are outlined in the color of whoever opened them.
+fn tunnels_to_dot(
+    valve_map: &HashMap<String, Valve>,
+    valve_list: &[String],
+    route: &[ValveOpening],
+) -> String {
+    let mut dot = String::from("graph tunnels {\n    node [shape=circle];\n");
+    for name in valve_list {
+        let valve = &valve_map[name];
+        let mut attributes = format!("label=\"{}\\n{}\"", name, valve.rate);
+        if valve.rate > 0 {
+            attributes.push_str(", style=filled, fillcolor=lightgrey");
+        }
+        if let Some(opening) = route.iter().find(|opening| &opening.valve == name) {
+            write!(
+                attributes,
+                ", color={}, penwidth=3, xlabel=\"minute {}\"",
+                ACTOR_COLORS[opening.actor % ACTOR_COLORS.len()],
+                opening.minute
+            )
+            .unwrap();
+        }
+        writeln!(dot, "    {} [{}];", name, attributes).unwrap();
+    }
+    for name in valve_list {
+        for to_valve in &valve_map[name].to_valves {
+            // Tunnels are listed from both ends, only write them once
+            if name < to_valve {
+                writeln!(dot, "    {} -- {};", name, to_valve).unwrap();
             }
         }
+    }
+    dot.push_str("}\n");
+    dot
+}
 
-        distance.get_mut(key).unwrap().remove(key);
-        if key != &start_valve {
-            distance.get_mut(key).unwrap().remove(&start_valve);
-        }
+/// The compressed graph the solver works on: the start and the valves with a
+/// flow rate, connected by their shortest distance. The moves of the chosen
+/// route are drawn as arrows in the color of the actor.
+fn distances_to_dot(graph: &ValveGraph, route: &[ValveOpening]) -> String {
+    let mut nodes: Vec<usize> = (0..graph.flowing).collect();
+    if graph.start >= graph.flowing {
+        nodes.push(graph.start);
     }
 
-    // Index
-    let mut idx_map: HashMap<String, u64> = HashMap::new();
-    for (i, value) in non_empty.iter().enumerate() {
-        idx_map.insert(value.clone(), i.try_into().unwrap());
+    let mut dot = String::from("digraph distances {\n    node [shape=circle];\n");
+    for &node in &nodes {
+        writeln!(
+            dot,
+            "    {} [label=\"{}\\n{}\"];",
+            graph.names[node], graph.names[node], graph.rates[node]
+        )
+        .unwrap();
+    }
+    for (i, &from) in nodes.iter().enumerate() {
+        for &to in &nodes[i + 1..] {
+            writeln!(
+                dot,
+                "    {} -> {} [dir=none, color=grey, label=\"{}\"];",
+                graph.names[from], graph.names[to], graph.distance[from][to]
+            )
+            .unwrap();
+        }
     }
 
-    // Solve
-    let b = (1 << non_empty.len()) - 1;
-    let mut result = 0;
-    let end_range = (b + 1) / 2;
-    // Range optimization for the case that it is more effective when both are working.
-    let start_range =( end_range as f64* 0.4) as u64;
-    let reduced_end_range =( end_range as f64* 0.7) as u64;
-
-
-    for i in start_range..reduced_end_range {
-        // let percentage = (i-start_range) * 100 / (reduced_end_range-start_range - 1);
-        // print!(
-        //     "\rStart solving {} of {} or {}%",
-        //     i-start_range,
-        //     reduced_end_range-start_range - 1,
-        //     percentage
-        // );
-
-        let r1 = dfs(
-            time,
-            start_valve.to_string(),
-            i,
-            &mut cache,
-            &valve_map,
-            &idx_map,
-            &distance,
-        );
-        let r2 = dfs(
-            time,
-            start_valve.to_string(),
-            b ^ i,
-            &mut cache,
-            &valve_map,
-            &idx_map,
-            &distance,
-        );
-        let rtry = r1 + r2;
-        result = result.max(rtry);
+    let actors = route
+        .iter()
+        .map(|opening| opening.actor + 1)
+        .max()
+        .unwrap_or(0);
+    for actor in 0..actors {
+        let mut openings: Vec<&ValveOpening> = route
+            .iter()
+            .filter(|opening| opening.actor == actor)
+            .collect();
+        openings.sort_by_key(|opening| opening.minute);
+
+        let mut from = &graph.names[graph.start];
+        for opening in openings {
+            writeln!(
+                dot,
+                "    {} -> {} [color={}, penwidth=3, label=\"{}\"];",
+                from,
+                opening.valve,
+                ACTOR_COLORS[actor % ACTOR_COLORS.len()],
+                opening.minute
+            )
+            .unwrap();
+            from = &opening.valve;
+        }
     }
+    dot.push_str("}\n");
+    dot
+}
+
+fn write_dot_files(
+    valve_map: &HashMap<String, Valve>,
+    valve_list: &[String],
+    graph: &ValveGraph,
+    options: &Options,
+    route: &[ValveOpening],
+) {
+    let Some(dot_dir) = &options.dot_dir else {
+        return;
+    };
+
+    fs::create_dir_all(dot_dir).expect("Failed creating the dot directory");
+    fs::write(
+        Path::new(dot_dir).join("tunnels.dot"),
+        tunnels_to_dot(valve_map, valve_list, route),
+    )
+    .expect("Failed writing the tunnel graph");
+    fs::write(
+        Path::new(dot_dir).join("distances.dot"),
+        distances_to_dot(graph, route),
+    )
+    .expect("Failed writing the distance graph");
+}
+
+fn run(input_file: &str) -> u64 {
+    let options = Options {
+        start_valve: String::from("AA"),
+        agents: 1,
+        time: 30,
+        dot_dir: None,
+    };
+    let (valve_map, valve_list) = parse_valves(input_file);
+    let graph = ValveGraph::new(&valve_map, &valve_list, &options.start_valve);
+    let (result, mut route) = solve(&graph, &options);
+    print_schedule(&mut route, options.time);
+    debug_assert_eq!(
+        route.iter().map(|opening| opening.released).sum::<u64>(),
+        result
+    );
+
+    println!("The end result is: {}", result);
+    result
+}
+
+fn run2(input_file: &str) -> u64 {
+    let options = Options {
+        start_valve: String::from("AA"),
+        agents: 2,
+        time: 26,
+        dot_dir: None,
+    };
+    let (valve_map, valve_list) = parse_valves(input_file);
+    let graph = ValveGraph::new(&valve_map, &valve_list, &options.start_valve);
+    let (result, mut route) = solve(&graph, &options);
+    print_schedule(&mut route, options.time);
+    debug_assert_eq!(
+        route.iter().map(|opening| opening.released).sum::<u64>(),
+        result
+    );
 
-    println!();
     println!("The end result is: {}", result);
+    result
 }
 
 fn main() {
     let input_path = get_input_path(file!());
     let input_file = input_path.to_str().unwrap();
 
+    if let Some(options) = Options::from_args() {
+        let (valve_map, valve_list) = parse_valves(input_file);
+        let graph = ValveGraph::new(&valve_map, &valve_list, &options.start_valve);
+        let (result, mut route) = solve(&graph, &options);
+        print_schedule(&mut route, options.time);
+        write_dot_files(&valve_map, &valve_list, &graph, &options, &route);
+        println!("The end result is: {}", result);
+        return;
+    }
+
     run(&input_file);
     run2(&input_file);
 }
@@ -354,18 +523,69 @@ fn main() {
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::distances_to_dot;
+    use crate::parse_valves;
     use crate::run;
     use crate::run2;
+    use crate::solve;
+    use crate::tunnels_to_dot;
+    use crate::Options;
+    use crate::ValveGraph;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 1651);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        assert_eq!(run2(input_path.to_str().unwrap()), 1707);
+    }
+
+    #[test]
+    fn test_more_agents_never_release_less() {
+        let input_path = get_test_input_path(file!());
+        let (valve_map, valve_list) = parse_valves(input_path.to_str().unwrap());
+        let mut previous = 0;
+        for agents in 1..=3 {
+            let options = Options {
+                start_valve: String::from("AA"),
+                agents,
+                time: 26,
+                dot_dir: None,
+            };
+            let graph = ValveGraph::new(&valve_map, &valve_list, &options.start_valve);
+            let (result, route) = solve(&graph, &options);
+            assert_eq!(
+                route.iter().map(|opening| opening.released).sum::<u64>(),
+                result
+            );
+            assert!(result >= previous);
+            previous = result;
+        }
+    }
+
+    #[test]
+    fn test_dot_export() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        let options = Options {
+            start_valve: String::from("AA"),
+            agents: 1,
+            time: 30,
+            dot_dir: None,
+        };
+        let (valve_map, valve_list) = parse_valves(input_file);
+        let graph = ValveGraph::new(&valve_map, &valve_list, &options.start_valve);
+        let (_, route) = solve(&graph, &options);
+
+        let tunnels = tunnels_to_dot(&valve_map, &valve_list, &route);
+        assert_eq!(tunnels.matches(" -- ").count(), 10);
+        assert!(tunnels.contains("DD [label=\"DD\\n20\", style=filled"));
+
+        let distances = distances_to_dot(&graph, &route);
+        assert!(distances.contains("AA -> DD [color=red, penwidth=3, label=\"2\"];"));
     }
 }
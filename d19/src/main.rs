@@ -1,8 +1,6 @@
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashMap;
 use std::fs::File;
-use std::hash::{Hash, Hasher};
 use std::io::{BufRead, BufReader};
+use std::thread;
 
 use regex::Regex;
 use utils::get_input_path;
@@ -23,11 +21,30 @@ struct MineralSet {
     pub geode: u64,
 }
 
-#[derive(Hash, PartialEq, Eq)]
-struct CacheKey {
-    pub robot_key: u64,
-    pub mineral_key: u64,
-    pub time: i64,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Robot {
+    Ore,
+    Clay,
+    Obsidian,
+    Geode,
+}
+
+#[derive(Debug, Clone)]
+struct BuildStep {
+    // Minute in which the robot is built, it starts collecting in the next one
+    pub minute: i64,
+    pub robot: Robot,
+    // Stockpile at the end of that minute
+    pub minerals: MineralSet,
+}
+
+struct Search<'a> {
+    pub blueprint: &'a Blueprint,
+    pub total_time: i64,
+    // Most geodes found so far and the builds that lead to them
+    pub best: u64,
+    pub best_builds: Vec<BuildStep>,
+    pub builds: Vec<BuildStep>,
 }
 
 #[derive(Debug)]
@@ -47,35 +64,29 @@ fn calc_ceil_of(blueprint_resource: &u64, remaining_resource: &u64, robot_amount
         as i64
 }
 
-// With nasty speedup hacks like '&& robots.obsidian <= 4'
-fn dfs(
-    blueprint: &Blueprint,
-    cache: &mut HashMap<CacheKey, u64>,
-    time: i64,
-    robots: RobotSet,
-    minerals: MineralSet,
-) -> u64 {
-    //  Is at the end
-    if time == 0 {
-        return minerals.geode;
-    }
-
-    // Caching
-    let hash_key = CacheKey {
-        robot_key: calculate_hash_robot(&robots),
-        mineral_key: calculate_hash_minerals(&minerals),
-        time,
-    };
-
-    if cache.contains_key(&hash_key) {
-        return cache.get(&hash_key).unwrap().clone();
-    }
+/// Most geodes that could still be cracked if a geode robot was built in
+/// every remaining minute.
+fn geode_upper_bound(time: i64, robots: &RobotSet, minerals: &MineralSet) -> u64 {
+    let time = time as u64;
+    minerals.geode + robots.geode * time + time * time.saturating_sub(1) / 2
+}
 
+// Branch and bound against the best result found so far for this blueprint
+fn dfs(search: &mut Search, time: i64, robots: RobotSet, minerals: MineralSet) -> u64 {
     // Preparing
+    let blueprint = search.blueprint;
     let mut max_val = minerals.geode + robots.geode * time as u64;
+    if max_val > search.best {
+        search.best = max_val;
+        search.best_builds = search.builds.clone();
+    }
 
-    // Walking
+    //  Is at the end or can not beat the best anymore
+    if time <= 1 || geode_upper_bound(time, &robots, &minerals) <= search.best {
+        return max_val;
+    }
 
+    // Walking
     if robots.obsidian > 0 {
         let wait_time = i64::max(
             0,
@@ -96,11 +107,17 @@ fn dfs(
             let mut new_robots = robots.clone();
             new_robots.geode += 1;
 
-            max_val = max_val.max(dfs(blueprint, cache, rem_time, new_robots, new_minerals));
+            search.builds.push(BuildStep {
+                minute: search.total_time - rem_time,
+                robot: Robot::Geode,
+                minerals: new_minerals.clone(),
+            });
+            max_val = max_val.max(dfs(search, rem_time, new_robots, new_minerals));
+            search.builds.pop();
         }
     }
 
-    if robots.obsidian < blueprint.max_spend.obsidian && robots.clay > 0 && robots.geode <= 4  {
+    if robots.obsidian < blueprint.max_spend.obsidian && robots.clay > 0 {
         let wait_time = i64::max(
             0,
             calc_ceil_of(
@@ -123,11 +140,17 @@ fn dfs(
             let mut new_robots = robots.clone();
             new_robots.obsidian += 1;
 
-            max_val = max_val.max(dfs(blueprint, cache, rem_time, new_robots, new_minerals));
+            search.builds.push(BuildStep {
+                minute: search.total_time - rem_time,
+                robot: Robot::Obsidian,
+                minerals: new_minerals.clone(),
+            });
+            max_val = max_val.max(dfs(search, rem_time, new_robots, new_minerals));
+            search.builds.pop();
         }
     }
 
-    if robots.clay < blueprint.max_spend.clay && robots.obsidian <= 4 {
+    if robots.clay < blueprint.max_spend.clay {
         let wait_time = i64::max(
             0,
             calc_ceil_of(&blueprint.clay_robot_ore_cost, &minerals.ore, &robots.ore),
@@ -140,11 +163,17 @@ fn dfs(
             let mut new_robots = robots.clone();
             new_robots.clay += 1;
 
-            max_val = max_val.max(dfs(blueprint, cache, rem_time, new_robots, new_minerals));
+            search.builds.push(BuildStep {
+                minute: search.total_time - rem_time,
+                robot: Robot::Clay,
+                minerals: new_minerals.clone(),
+            });
+            max_val = max_val.max(dfs(search, rem_time, new_robots, new_minerals));
+            search.builds.pop();
         }
     }
 
-    if robots.ore < blueprint.max_spend.ore && robots.clay <= 2 {
+    if robots.ore < blueprint.max_spend.ore {
         let wait_time = i64::max(
             0,
             calc_ceil_of(&blueprint.ore_robot_ore_cost, &minerals.ore, &robots.ore),
@@ -157,12 +186,17 @@ fn dfs(
             let mut new_robots = robots.clone();
             new_robots.ore += 1;
 
-            max_val = max_val.max(dfs(blueprint, cache, rem_time, new_robots, new_minerals));
+            search.builds.push(BuildStep {
+                minute: search.total_time - rem_time,
+                robot: Robot::Ore,
+                minerals: new_minerals.clone(),
+            });
+            max_val = max_val.max(dfs(search, rem_time, new_robots, new_minerals));
+            search.builds.pop();
         }
     }
 
-    cache.insert(hash_key, max_val);
-    return max_val;
+    max_val
 }
 
 fn update_minerals(mut minerals: MineralSet, new_robots: &RobotSet, times: &u64) -> MineralSet {
@@ -173,60 +207,42 @@ fn update_minerals(mut minerals: MineralSet, new_robots: &RobotSet, times: &u64)
     return minerals;
 }
 
-fn calculate_hash_minerals(ores: &MineralSet) -> u64 {
-    let mut o_hasher: DefaultHasher = DefaultHasher::new();
-    ores.hash(&mut o_hasher);
-    o_hasher.finish()
-}
-
-fn calculate_hash_robot(ores: &RobotSet) -> u64 {
-    let mut r_hasher: DefaultHasher = DefaultHasher::new();
-    ores.hash(&mut r_hasher);
-    r_hasher.finish()
-}
-
-fn run(input_file: &str) {
-    // Vars
+fn parse_blueprints(input_file: &str) -> Vec<Blueprint> {
     let mut blueprints: Vec<Blueprint> = Vec::new();
 
-    // Parse
     let file = File::open(input_file).unwrap();
     let reader = BufReader::new(file);
-    for (_index, line) in reader.lines().enumerate() {
+    let rgx =
+        Regex::new(r#"Blueprint ([-0-9]+): Each ore robot costs ([-0-9]+) ore. Each clay robot costs ([-0-9]+) ore. Each obsidian robot costs ([-0-9]+) ore and ([-0-9]+) clay. Each geode robot costs ([-0-9]+) ore and ([-0-9]+) obsidian."#)
+            .unwrap();
+    for line in reader.lines() {
         let trimmed_line = line.unwrap().trim().to_string();
 
         if trimmed_line.is_empty() {
             continue;
         }
 
-        let rgx =
-            Regex::new(r#"Blueprint ([-0-9]+): Each ore robot costs ([-0-9]+) ore. Each clay robot costs ([-0-9]+) ore. Each obsidian robot costs ([-0-9]+) ore and ([-0-9]+) clay. Each geode robot costs ([-0-9]+) ore and ([-0-9]+) obsidian."#)
-                .unwrap();
-
         let captures = rgx.captures(&trimmed_line).unwrap();
         let blueprint_number = captures[1].parse().unwrap();
-        let ore_robot_ore_cost = captures[2].parse().unwrap();
+        let ore_robot_ore_cost: u64 = captures[2].parse().unwrap();
         let clay_robot_ore_cost = captures[3].parse().unwrap();
         let obsidian_robot_ore_cost = captures[4].parse().unwrap();
         let obsidian_robot_clay_cost = captures[5].parse().unwrap();
         let geode_robot_ore_cost = captures[6].parse().unwrap();
         let geode_robot_obsidian_cost = captures[7].parse().unwrap();
 
-        let mut max_spend = MineralSet {
-            ore: 0,
-            clay: 0,
-            obsidian: 0,
+        // Only one robot can be built per minute, so there is no point in
+        // mining more of a mineral per minute than the most expensive robot needs
+        let max_spend = MineralSet {
+            ore: ore_robot_ore_cost
+                .max(clay_robot_ore_cost)
+                .max(obsidian_robot_ore_cost)
+                .max(geode_robot_ore_cost),
+            clay: obsidian_robot_clay_cost,
+            obsidian: geode_robot_obsidian_cost,
             geode: u64::MAX,
         };
 
-        max_spend.ore = max_spend.ore.max(ore_robot_ore_cost);
-        max_spend.ore = max_spend.ore.max(clay_robot_ore_cost);
-        max_spend.ore = max_spend.ore.max(obsidian_robot_ore_cost);
-        max_spend.ore = max_spend.ore.max(geode_robot_ore_cost);
-
-        max_spend.clay = max_spend.ore.max(obsidian_robot_clay_cost);
-        max_spend.obsidian = max_spend.obsidian.max(geode_robot_obsidian_cost);
-
         let blueprint = Blueprint {
             blueprint_number,
             ore_robot_ore_cost,
@@ -240,125 +256,108 @@ fn run(input_file: &str) {
         blueprints.push(blueprint);
     }
 
-    // Solve
-    let mut result_map: HashMap<u64, u64> = HashMap::new();
+    blueprints
+}
 
-    let mut final_result = 0;
-    for blueprint in &blueprints {
-        // Start values
-        let mut cache: HashMap<CacheKey, u64> = HashMap::new();
-        let time = 24;
-        let robot_set = RobotSet {
-            ore: 1,
-            clay: 0,
-            obsidian: 0,
-            geode: 0,
-        };
+fn max_geodes(blueprint: &Blueprint, time: i64) -> (u64, Vec<BuildStep>) {
+    // Start values
+    let robot_set = RobotSet {
+        ore: 1,
+        clay: 0,
+        obsidian: 0,
+        geode: 0,
+    };
 
-        let ore_set = MineralSet {
-            ore: 0,
-            clay: 0,
-            obsidian: 0,
-            geode: 0,
+    let ore_set = MineralSet {
+        ore: 0,
+        clay: 0,
+        obsidian: 0,
+        geode: 0,
+    };
+    let mut search = Search {
+        blueprint,
+        total_time: time,
+        best: 0,
+        best_builds: Vec::new(),
+        builds: Vec::new(),
+    };
+    let result = dfs(&mut search, time, robot_set, ore_set);
+    (result, search.best_builds)
+}
+
+fn print_build_order(builds: &[BuildStep]) {
+    for step in builds {
+        let robot = match step.robot {
+            Robot::Ore => "an ore",
+            Robot::Clay => "a clay",
+            Robot::Obsidian => "an obsidian",
+            Robot::Geode => "a geode",
         };
-        let result = dfs(blueprint, &mut cache, time, robot_set, ore_set);
-        result_map.insert(blueprint.blueprint_number.clone(), result.clone());
-        final_result += result * blueprint.blueprint_number;
-        println!("Blueprint {} has a score of {}. That results in a quality of {}", blueprint.blueprint_number, result, result * blueprint.blueprint_number)
+        println!(
+            "  Minute {:>2}: build {} robot, stockpile {} ore, {} clay, {} obsidian, {} geode",
+            step.minute,
+            robot,
+            step.minerals.ore,
+            step.minerals.clay,
+            step.minerals.obsidian,
+            step.minerals.geode
+        );
     }
+}
 
+/// Solves every blueprint on its own thread.
+fn max_geodes_parallel(blueprints: &[Blueprint], time: i64) -> Vec<(u64, Vec<BuildStep>)> {
+    thread::scope(|scope| {
+        let handles: Vec<_> = blueprints
+            .iter()
+            .map(|blueprint| scope.spawn(move || max_geodes(blueprint, time)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn run(input_file: &str) -> u64 {
+    let blueprints = parse_blueprints(input_file);
+
+    // Solve
+    let mut final_result = 0;
+    let results = max_geodes_parallel(&blueprints, 24);
+    for (blueprint, (result, builds)) in blueprints.iter().zip(results) {
+        final_result += result * blueprint.blueprint_number;
+        println!(
+            "Blueprint {} has a score of {}. That results in a quality of {}",
+            blueprint.blueprint_number,
+            result,
+            result * blueprint.blueprint_number
+        );
+        print_build_order(&builds);
+    }
 
     println!("Final Result {}", final_result);
+    final_result
 }
 
-fn run2(input_file: &str) {
-        // Vars
-        let mut blueprints: Vec<Blueprint> = Vec::new();
-
-        // Parse
-        let file = File::open(input_file).unwrap();
-        let reader = BufReader::new(file);
-        for (_index, line) in reader.lines().enumerate() {
-            let trimmed_line = line.unwrap().trim().to_string();
-    
-            if trimmed_line.is_empty() {
-                continue;
-            }
-    
-            let rgx =
-                Regex::new(r#"Blueprint ([-0-9]+): Each ore robot costs ([-0-9]+) ore. Each clay robot costs ([-0-9]+) ore. Each obsidian robot costs ([-0-9]+) ore and ([-0-9]+) clay. Each geode robot costs ([-0-9]+) ore and ([-0-9]+) obsidian."#)
-                    .unwrap();
-    
-            let captures = rgx.captures(&trimmed_line).unwrap();
-            let blueprint_number = captures[1].parse().unwrap();
-            let ore_robot_ore_cost = captures[2].parse().unwrap();
-            let clay_robot_ore_cost = captures[3].parse().unwrap();
-            let obsidian_robot_ore_cost = captures[4].parse().unwrap();
-            let obsidian_robot_clay_cost = captures[5].parse().unwrap();
-            let geode_robot_ore_cost = captures[6].parse().unwrap();
-            let geode_robot_obsidian_cost = captures[7].parse().unwrap();
-    
-            let mut max_spend = MineralSet {
-                ore: 0,
-                clay: 0,
-                obsidian: 0,
-                geode: u64::MAX,
-            };
-    
-            max_spend.ore = max_spend.ore.max(ore_robot_ore_cost);
-            max_spend.ore = max_spend.ore.max(clay_robot_ore_cost);
-            max_spend.ore = max_spend.ore.max(obsidian_robot_ore_cost);
-            max_spend.ore = max_spend.ore.max(geode_robot_ore_cost);
-    
-            max_spend.clay = max_spend.ore.max(obsidian_robot_clay_cost);
-            max_spend.obsidian = max_spend.obsidian.max(geode_robot_obsidian_cost);
-    
-            let blueprint = Blueprint {
-                blueprint_number,
-                ore_robot_ore_cost,
-                clay_robot_ore_cost,
-                obsidian_robot_ore_cost,
-                obsidian_robot_clay_cost,
-                geode_robot_ore_cost,
-                geode_robot_obsidian_cost,
-                max_spend,
-            };
-            blueprints.push(blueprint);
-        }
-    
-        // Solve
-        let mut result_map: HashMap<u64, u64> = HashMap::new();
-    
-        let mut final_result = 1;
-        let mut break_after = 3;
-        for blueprint in &blueprints {
-            // Start values
-            let mut cache: HashMap<CacheKey, u64> = HashMap::new();
-            let time = 32;
-            let robot_set = RobotSet {
-                ore: 1,
-                clay: 0,
-                obsidian: 0,
-                geode: 0,
-            };
-    
-            let ore_set = MineralSet {
-                ore: 0,
-                clay: 0,
-                obsidian: 0,
-                geode: 0,
-            };
-            let result = dfs(blueprint, &mut cache, time, robot_set, ore_set);
-            result_map.insert(blueprint.blueprint_number.clone(), result.clone());
-            final_result *= result;
-            println!("Blueprint {} has a score of {}", blueprint.blueprint_number, result);
-            break_after -= 1;
-            if break_after == 0{
-                break;
-            }
-        }
-    
-        println!("Final Result {}", final_result);
+fn run2(input_file: &str) -> u64 {
+    let mut blueprints = parse_blueprints(input_file);
+    blueprints.truncate(3);
+
+    // Solve
+    let mut final_result = 1;
+    let results = max_geodes_parallel(&blueprints, 32);
+    for (blueprint, (result, builds)) in blueprints.iter().zip(results) {
+        final_result *= result;
+        println!(
+            "Blueprint {} has a score of {}",
+            blueprint.blueprint_number, result
+        );
+        print_build_order(&builds);
+    }
+
+    println!("Final Result {}", final_result);
+    final_result
 }
 
 fn main() {
@@ -373,18 +372,38 @@ fn main() {
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::max_geodes;
+    use crate::parse_blueprints;
     use crate::run;
     use crate::run2;
+    use crate::Robot;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 33);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        assert_eq!(run2(input_path.to_str().unwrap()), 56 * 62);
+    }
+
+    #[test]
+    fn test_build_order_replays_to_score() {
+        let input_path = get_test_input_path(file!());
+        for blueprint in parse_blueprints(input_path.to_str().unwrap()) {
+            let time = 24;
+            let (result, builds) = max_geodes(&blueprint, time);
+            // Every geode robot cracks one geode per minute after it was built
+            let geodes: i64 = builds
+                .iter()
+                .filter(|step| step.robot == Robot::Geode)
+                .map(|step| time - step.minute)
+                .sum();
+            assert_eq!(geodes as u64, result);
+            assert!(builds.windows(2).all(|w| w[0].minute < w[1].minute));
+        }
     }
 }
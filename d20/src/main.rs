@@ -1,29 +1,229 @@
-#![feature(linked_list_cursors)]
+#![cfg_attr(test, feature(linked_list_cursors))]
+#[cfg(test)]
 use std::fs::File;
+#[cfg(test)]
 use std::io::{BufRead, BufReader};
+#[cfg(test)]
 use std::rc::Rc;
 
 use utils::get_input_path;
 
+#[cfg(test)]
 #[derive(PartialEq)]
 struct NumberNode {
     pub number: i64,
     pub original_position: usize,
 }
 
+#[cfg(test)]
 impl std::fmt::Display for NumberNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.number)
     }
 }
 
+#[cfg(test)]
 impl std::fmt::Debug for NumberNode {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.number)
     }
 }
 
-fn run(input_file: &str) {
+const NIL: usize = usize::MAX;
+
+/// Implicit treap over the positions of the mixed sequence. Node `i` is the
+/// `i`-th number of the input, the parent links allow looking up the current
+/// position of a number in logarithmic time.
+struct MixTree {
+    left: Vec<usize>,
+    right: Vec<usize>,
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    priority: Vec<u64>,
+    root: usize,
+}
+
+impl MixTree {
+    fn new(length: usize) -> Self {
+        // Xorshift, the priorities only have to be spread out
+        let mut seed: u64 = 0x2545F4914F6CDD1D;
+        let priority = (0..length)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed
+            })
+            .collect();
+
+        let mut tree = MixTree {
+            left: vec![NIL; length],
+            right: vec![NIL; length],
+            parent: vec![NIL; length],
+            size: vec![1; length],
+            priority,
+            root: NIL,
+        };
+        for node in 0..length {
+            tree.root = tree.merge(tree.root, node);
+        }
+        tree
+    }
+
+    fn len(&self) -> usize {
+        self.size_of(self.root)
+    }
+
+    fn size_of(&self, node: usize) -> usize {
+        if node == NIL {
+            0
+        } else {
+            self.size[node]
+        }
+    }
+
+    fn update(&mut self, node: usize) {
+        self.size[node] = 1 + self.size_of(self.left[node]) + self.size_of(self.right[node]);
+        for child in [self.left[node], self.right[node]] {
+            if child != NIL {
+                self.parent[child] = node;
+            }
+        }
+    }
+
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        let root = if a == NIL {
+            b
+        } else if b == NIL {
+            a
+        } else if self.priority[a] > self.priority[b] {
+            self.right[a] = self.merge(self.right[a], b);
+            self.update(a);
+            a
+        } else {
+            self.left[b] = self.merge(a, self.left[b]);
+            self.update(b);
+            b
+        };
+        if root != NIL {
+            self.parent[root] = NIL;
+        }
+        root
+    }
+
+    /// Splits off the first `count` positions.
+    fn split(&mut self, node: usize, count: usize) -> (usize, usize) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+        let left_size = self.size_of(self.left[node]);
+        let (a, b) = if left_size >= count {
+            let (a, b) = self.split(self.left[node], count);
+            self.left[node] = b;
+            self.update(node);
+            (a, node)
+        } else {
+            let (a, b) = self.split(self.right[node], count - left_size - 1);
+            self.right[node] = a;
+            self.update(node);
+            (node, b)
+        };
+        for root in [a, b] {
+            if root != NIL {
+                self.parent[root] = NIL;
+            }
+        }
+        (a, b)
+    }
+
+    fn index_of(&self, mut node: usize) -> usize {
+        let mut index = self.size_of(self.left[node]);
+        while self.parent[node] != NIL {
+            let parent = self.parent[node];
+            if self.right[parent] == node {
+                index += self.size_of(self.left[parent]) + 1;
+            }
+            node = parent;
+        }
+        index
+    }
+
+    /// Moves the node by `offset` positions through the circular sequence.
+    fn shift(&mut self, node: usize, offset: i64) {
+        let length = self.len();
+        let index = self.index_of(node);
+        let (before, rest) = self.split(self.root, index);
+        let (_, after) = self.split(rest, 1);
+        let others = self.merge(before, after);
+
+        let new_index = (index as i64 + offset).rem_euclid(length as i64 - 1) as usize;
+        let (before, after) = self.split(others, new_index);
+        let before = self.merge(before, node);
+        self.root = self.merge(before, after);
+    }
+
+    fn in_order(&self) -> Vec<usize> {
+        let mut order = Vec::with_capacity(self.len());
+        let mut stack = Vec::new();
+        let mut node = self.root;
+        while node != NIL || !stack.is_empty() {
+            while node != NIL {
+                stack.push(node);
+                node = self.left[node];
+            }
+            node = stack.pop().unwrap();
+            order.push(node);
+            node = self.right[node];
+        }
+        order
+    }
+}
+
+fn parse_numbers(input: &str) -> Vec<i64> {
+    input
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| line.parse::<i64>().unwrap())
+        .collect()
+}
+
+fn mix(numbers: &[i64], rounds: usize) -> Vec<i64> {
+    let mut tree = MixTree::new(numbers.len());
+    if numbers.len() > 1 {
+        for _ in 0..rounds {
+            for (node, number) in numbers.iter().enumerate() {
+                tree.shift(node, *number);
+            }
+        }
+    }
+    tree.in_order()
+        .into_iter()
+        .map(|node| numbers[node])
+        .collect()
+}
+
+fn grove_coordinates(mixed: &[i64]) -> i64 {
+    let null_idx = mixed.iter().position(|number| *number == 0).unwrap();
+    [1000, 2000, 3000]
+        .iter()
+        .map(|offset| mixed[(null_idx + offset) % mixed.len()])
+        .sum()
+}
+
+fn solve(input_file: &str, key: i64, rounds: usize) -> i64 {
+    let numbers: Vec<i64> = parse_numbers(&std::fs::read_to_string(input_file).unwrap())
+        .into_iter()
+        .map(|number| number * key)
+        .collect();
+    let result = grove_coordinates(&mix(&numbers, rounds));
+    println!("The final result is {}", result);
+    result
+}
+
+// Reference implementation, the tree based mixing is checked against it in the tests
+#[cfg(test)]
+fn run(input_file: &str) -> i64 {
     // Preamble
     let mut numbers = Vec::new();
     let mut numbers_in_order = Vec::new();
@@ -100,9 +300,11 @@ fn run(input_file: &str) {
         "r1000 is {} r2000 is {} r3000 is {} the final result is {}",
         r1000, r2000, r3000, result
     );
+    result
 }
 
-fn run2(input_file: &str) {
+#[cfg(test)]
+fn run2(input_file: &str) -> i64 {
     // Preamble
     let mut numbers = Vec::new();
     let mut numbers_in_order = Vec::new();
@@ -164,7 +366,7 @@ fn run2(input_file: &str) {
                 }
             }
         }
-        
+
         // println!("{:?}", numbers);
     }
     // Result
@@ -182,6 +384,7 @@ fn run2(input_file: &str) {
         "r1000 is {} r2000 is {} r3000 is {} the final result is {}",
         r1000, r2000, r3000, result
     );
+    result
 }
 
 fn main() {
@@ -190,26 +393,62 @@ fn main() {
 
     println!("{:?}", input_file);
 
-    run(&input_file);
-    run2(&input_file);
+    solve(input_file, 1, 1);
+    solve(input_file, 811589153, 10);
 }
 
 #[cfg(test)]
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::mix;
+    use crate::parse_numbers;
     use crate::run;
     use crate::run2;
+    use crate::solve;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        let input_file = input_path.to_str().unwrap();
+        assert_eq!(solve(input_file, 1, 1), run(input_file));
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        let input_file = input_path.to_str().unwrap();
+        assert_eq!(solve(input_file, 811589153, 10), run2(input_file));
+    }
+
+    #[test]
+    fn test_mix_matches_reference_on_random_input() {
+        let mut seed: i64 = 42;
+        let mut numbers: Vec<i64> = (0..200)
+            .map(|_| {
+                seed = (seed * 1103515245 + 12345) % 2147483648;
+                seed % 2001 - 1000
+            })
+            .filter(|number| *number != 0)
+            .collect();
+        numbers.insert(57, 0);
+        let content: Vec<String> = numbers.iter().map(|number| number.to_string()).collect();
+        assert_eq!(parse_numbers(&content.join("\n")), numbers);
+
+        // The reference implementation only reads files
+        let input_path =
+            std::env::temp_dir().join(format!("d20_random_input_{}.txt", std::process::id()));
+        std::fs::write(&input_path, content.join("\n")).unwrap();
+        let input_file = input_path.to_str().unwrap();
+        let results = (
+            solve(input_file, 1, 1),
+            run(input_file),
+            solve(input_file, 811589153, 10),
+            run2(input_file),
+        );
+        std::fs::remove_file(&input_path).unwrap();
+        assert_eq!(results.0, results.1);
+        assert_eq!(results.2, results.3);
+        assert_eq!(mix(&[0], 3), vec![0]);
     }
 }
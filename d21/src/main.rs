@@ -1,246 +1,456 @@
-use std::cell::RefCell;
-use std::collections::{HashMap, VecDeque};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::rc::Rc;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
 
-use regex::Regex;
 use utils::get_input_path;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl Operator {
+    fn parse(operator: &str) -> Operator {
+        match operator {
+            "+" => Operator::Add,
+            "-" => Operator::Sub,
+            "*" => Operator::Mul,
+            "/" => Operator::Div,
+            _ => panic!("Unknown operator {}", operator),
+        }
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/// Job of a monkey, the operands are ids into the `Monkeys` arena.
 #[derive(Debug)]
-struct Operation {
-    pub name: String,
-    pub name1: String,
-    pub operation: String,
-    pub name2: String,
-    pub number: Option<i64>,
-    pub should_be: i64,
+enum Job {
+    Number(i64),
+    Operation(usize, Operator, usize),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SolveError {
+    UnknownMonkey(String),
+    Cycle(String),
+    DivisionByZero,
+    Overflow,
+    NonLinear,
+    NoUniqueSolution,
 }
 
-fn get_number(name: &str, items: Rc<RefCell<HashMap<String, Rc<RefCell<Operation>>>>>) -> i64 {
-    let item_rc = items.borrow_mut().get_mut(name).unwrap().clone();
-    let mut item = (*item_rc).borrow_mut();
-    if item.number.is_none() {
-        item.number = Some(match item.operation.as_str() {
-            "+" => get_number(&item.name1, items.clone()) + get_number(&item.name2, items.clone()),
-            "-" => get_number(&item.name1, items.clone()) - get_number(&item.name2, items.clone()),
-            "*" => get_number(&item.name1, items.clone()) * get_number(&item.name2, items.clone()),
-            "/" => get_number(&item.name1, items.clone()) / get_number(&item.name2, items.clone()),
-            _ => panic!("Should not be here"),
-        });
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SolveError::UnknownMonkey(name) => write!(f, "there is no monkey called {}", name),
+            SolveError::Cycle(name) => write!(f, "monkey {} depends on itself", name),
+            SolveError::DivisionByZero => write!(f, "division by zero"),
+            SolveError::Overflow => write!(f, "a number does not fit into an i128"),
+            SolveError::NonLinear => write!(f, "the equation is not linear in the variable"),
+            SolveError::NoUniqueSolution => write!(f, "the equation has no unique solution"),
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
     }
+}
 
-    return item.number.unwrap();
+/// Exact fraction, always reduced and with a positive denominator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Rational {
+    pub numerator: i128,
+    pub denominator: i128,
 }
 
-fn find_item(
-    from: &str,
-    to: &str,
-    items: Rc<RefCell<HashMap<String, Rc<RefCell<Operation>>>>>,
-) -> Option<VecDeque<Rc<RefCell<Operation>>>> {
-    let item_rc = (*items).borrow().get(from).unwrap().clone();
-    let item = (*item_rc).borrow();
-
-    if item.name1 == to || item.name2 == to {
-        let next_item = (*items).borrow().get(to).unwrap().clone();
-        return Some(VecDeque::from([item_rc.clone(), next_item]));
+impl Rational {
+    fn new(numerator: i128, denominator: i128) -> Self {
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Rational {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        }
     }
 
-    if item.number.is_some() {
-        return None;
+    fn from_integer(value: i128) -> Self {
+        Rational {
+            numerator: value,
+            denominator: 1,
+        }
     }
 
-    let result = find_item(&item.name1, to, items.clone());
-    if result.is_some() {
-        let mut results = result.unwrap();
-        results.push_front(item_rc.clone());
-        return Some(results);
+    fn is_zero(&self) -> bool {
+        self.numerator == 0
     }
 
-    let result2 = find_item(&item.name2, to, items.clone());
-    if result2.is_some() {
-        let mut results = result2.unwrap();
-        results.push_front(item_rc.clone());
-        return Some(results);
+    fn apply(self, operator: Operator, other: Rational) -> Result<Rational, SolveError> {
+        let mul = |a: i128, b: i128| a.checked_mul(b).ok_or(SolveError::Overflow);
+        let (numerator, denominator) = match operator {
+            Operator::Add => (
+                mul(self.numerator, other.denominator)?
+                    .checked_add(mul(other.numerator, self.denominator)?)
+                    .ok_or(SolveError::Overflow)?,
+                mul(self.denominator, other.denominator)?,
+            ),
+            Operator::Sub => (
+                mul(self.numerator, other.denominator)?
+                    .checked_sub(mul(other.numerator, self.denominator)?)
+                    .ok_or(SolveError::Overflow)?,
+                mul(self.denominator, other.denominator)?,
+            ),
+            Operator::Mul => (
+                mul(self.numerator, other.numerator)?,
+                mul(self.denominator, other.denominator)?,
+            ),
+            Operator::Div => {
+                if other.is_zero() {
+                    return Err(SolveError::DivisionByZero);
+                }
+                (
+                    mul(self.numerator, other.denominator)?,
+                    mul(self.denominator, other.numerator)?,
+                )
+            }
+        };
+        Ok(Rational::new(numerator, denominator))
     }
+}
 
-    return None;
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
 }
 
-fn recalculate(
-    item_rc: Rc<RefCell<Operation>>,
-    given: String,
-    is_left: bool,
-    items: Rc<RefCell<HashMap<String, Rc<RefCell<Operation>>>>>,
-) -> i64 {
-    let item = (*item_rc).borrow();
-
-    match item.operation.as_str() {
-        "+" => item.should_be - get_number(given.as_str(), items.clone()),
-        "-" => {
-            if is_left {
-                item.should_be + get_number(given.as_str(), items.clone())
-            } else {
-                get_number(given.as_str(), items.clone()) - item.should_be
-            }
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Constant(Rational),
+    Variable(String),
+    Binary(Box<Expr>, Operator, Box<Expr>),
+}
+
+/// Written out with an explicit stack, expressions can be far deeper than
+/// the call stack.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        enum Token<'a> {
+            Expr(&'a Expr),
+            Operator(Operator),
+            Close,
         }
-        "*" => item.should_be / get_number(given.as_str(), items.clone()),
-        "/" => {
-            if is_left {
-                item.should_be * get_number(given.as_str(), items.clone())
-            } else {
-                get_number(given.as_str(), items.clone()) / item.should_be
+
+        let mut stack = vec![Token::Expr(self)];
+        while let Some(token) = stack.pop() {
+            match token {
+                Token::Expr(Expr::Constant(value)) => write!(f, "{}", value)?,
+                Token::Expr(Expr::Variable(name)) => write!(f, "{}", name)?,
+                Token::Expr(Expr::Binary(left, operator, right)) => {
+                    write!(f, "(")?;
+                    stack.push(Token::Close);
+                    stack.push(Token::Expr(right));
+                    stack.push(Token::Operator(*operator));
+                    stack.push(Token::Expr(left));
+                }
+                Token::Operator(operator) => write!(f, " {} ", operator)?,
+                Token::Close => write!(f, ")")?,
             }
         }
-        _ => panic!("Something is wrong"),
+        Ok(())
     }
 }
 
-fn run(input_file: &str) {
-    // Prepare
-    let mut items: HashMap<String, Rc<RefCell<Operation>>> = HashMap::new();
+/// Takes the children apart one by one, the default drop would recurse.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let placeholder = || Expr::Constant(Rational::from_integer(0));
+        let mut stack = Vec::new();
+        if let Expr::Binary(left, _, right) = self {
+            stack.push(std::mem::replace(left.as_mut(), placeholder()));
+            stack.push(std::mem::replace(right.as_mut(), placeholder()));
+        }
+        while let Some(mut expr) = stack.pop() {
+            if let Expr::Binary(left, _, right) = &mut expr {
+                stack.push(std::mem::replace(left.as_mut(), placeholder()));
+                stack.push(std::mem::replace(right.as_mut(), placeholder()));
+            }
+        }
+    }
+}
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-    for (_index, line) in reader.lines().enumerate() {
-        let line = line.unwrap().trim().to_string();
-        let rgx = Regex::new(r#"(\D\D\D\D): (\D\D\D\D) ([+\-*/]) (\D\D\D\D)"#).unwrap();
-
-        let rgx_result = rgx.captures(&line);
-        if rgx_result.is_some() {
-            let captures = rgx_result.unwrap();
-
-            let name = &captures[1];
-            let name1 = &captures[2];
-            let operation = &captures[3];
-            let name2 = &captures[4];
-
-            items.insert(
-                name.to_string(),
-                Rc::new(RefCell::new(Operation {
-                    name: name.to_string(),
-                    name1: name1.to_string(),
-                    operation: operation.to_string(),
-                    name2: name2.to_string(),
-                    number: None,
-                    should_be: 0,
-                })),
-            );
-        } else {
-            let rgx2 = Regex::new(r#"(\D\D\D\D): (\d+)"#).unwrap();
-            let rgx2_captures = rgx2.captures(&line).unwrap();
-            let name = &rgx2_captures[1];
-            let number = &rgx2_captures[2].parse().unwrap();
-            items.insert(
-                name.to_string(),
-                Rc::new(RefCell::new(Operation {
-                    name: name.to_string(),
-                    name1: String::from(""),
-                    operation: String::from(""),
-                    name2: String::from(""),
-                    number: Some(*number),
-                    should_be: 0,
-                })),
-            );
+/// An expression as `coefficient * variable + constant`.
+type LinearForm = (Rational, Rational);
+
+/// Linear form of `left operator right`, if it is still linear.
+fn combine_linear(
+    (a1, b1): LinearForm,
+    operator: Operator,
+    (a2, b2): LinearForm,
+) -> Result<LinearForm, SolveError> {
+    match operator {
+        Operator::Add | Operator::Sub => Ok((a1.apply(operator, a2)?, b1.apply(operator, b2)?)),
+        Operator::Mul if a1.is_zero() => {
+            Ok((b1.apply(Operator::Mul, a2)?, b1.apply(Operator::Mul, b2)?))
+        }
+        Operator::Mul if a2.is_zero() => {
+            Ok((a1.apply(Operator::Mul, b2)?, b1.apply(Operator::Mul, b2)?))
         }
+        Operator::Div if a2.is_zero() => {
+            Ok((a1.apply(Operator::Div, b2)?, b1.apply(Operator::Div, b2)?))
+        }
+        _ => Err(SolveError::NonLinear),
     }
+}
 
-    let items_rc: Rc<RefCell<HashMap<String, Rc<RefCell<Operation>>>>> =
-        Rc::new(RefCell::new(items));
+/// Solves `left = right` for the variable, it may show up on both sides.
+fn solve_equation((a1, b1): LinearForm, (a2, b2): LinearForm) -> Result<Rational, SolveError> {
+    let coefficient = a1.apply(Operator::Sub, a2)?;
+    if coefficient.is_zero() {
+        return Err(SolveError::NoUniqueSolution);
+    }
+    b2.apply(Operator::Sub, b1)?
+        .apply(Operator::Div, coefficient)
+}
 
-    let result = get_number("root", items_rc.clone());
-    println!("Result: {}", result);
+/// Every monkey interned by id, `names[id]` and `jobs[id]` belong together.
+struct Monkeys {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    jobs: Vec<Job>,
 }
 
-fn run2(input_file: &str) {
-    // Prepare
-    let mut items: HashMap<String, Rc<RefCell<Operation>>> = HashMap::new();
+impl Monkeys {
+    fn parse(input: &str) -> Result<Monkeys, SolveError> {
+        let lines: Vec<&str> = input
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let mut names = Vec::new();
+        let mut ids = HashMap::new();
+        for line in &lines {
+            let (name, _) = line
+                .split_once(": ")
+                .unwrap_or_else(|| panic!("Can not parse {}", line));
+            ids.insert(name.to_string(), names.len());
+            names.push(name.to_string());
+        }
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-    for (_index, line) in reader.lines().enumerate() {
-        let line = line.unwrap().trim().to_string();
-        let rgx = Regex::new(r#"(\D\D\D\D): (\D\D\D\D) ([+\-*/]) (\D\D\D\D)"#).unwrap();
-
-        let rgx_result = rgx.captures(&line);
-        if rgx_result.is_some() {
-            let captures = rgx_result.unwrap();
-
-            let name = &captures[1];
-            let name1 = &captures[2];
-            let operation = &captures[3];
-            let name2 = &captures[4];
-
-            items.insert(
-                name.to_string(),
-                Rc::new(RefCell::new(Operation {
-                    name: name.to_string(),
-                    name1: name1.to_string(),
-                    operation: operation.to_string(),
-                    name2: name2.to_string(),
-                    number: None,
-                    should_be: 0,
-                })),
-            );
-        } else {
-            let rgx2 = Regex::new(r#"(\D\D\D\D): (\d+)"#).unwrap();
-            let rgx2_captures = rgx2.captures(&line).unwrap();
-            let name = &rgx2_captures[1];
-            let number = &rgx2_captures[2].parse().unwrap();
-            items.insert(
-                name.to_string(),
-                Rc::new(RefCell::new(Operation {
-                    name: name.to_string(),
-                    name1: String::from(""),
-                    operation: String::from(""),
-                    name2: String::from(""),
-                    number: Some(*number),
-                    should_be: 0,
-                })),
-            );
+        let id_of = |name: &str| {
+            ids.get(name)
+                .copied()
+                .ok_or_else(|| SolveError::UnknownMonkey(name.to_string()))
+        };
+        let mut jobs = Vec::with_capacity(lines.len());
+        for line in &lines {
+            let (name, job) = line.split_once(": ").unwrap();
+            let parts: Vec<&str> = job.split_whitespace().collect();
+            jobs.push(match parts[..] {
+                [number] => Job::Number(number.parse().unwrap()),
+                [name1, operator, name2] => {
+                    Job::Operation(id_of(name1)?, Operator::parse(operator), id_of(name2)?)
+                }
+                _ => panic!("Can not parse the job of {}", name),
+            });
         }
+
+        Ok(Monkeys { names, ids, jobs })
     }
 
-    let items_rc: Rc<RefCell<HashMap<String, Rc<RefCell<Operation>>>>> =
-        Rc::new(RefCell::new(items));
+    fn id(&self, name: &str) -> Result<usize, SolveError> {
+        self.ids
+            .get(name)
+            .copied()
+            .ok_or_else(|| SolveError::UnknownMonkey(name.to_string()))
+    }
 
-    // Solution Prepareration
-    let chain = find_item("root", "humn", items_rc.clone()).unwrap();
-    {
-        let root_rc = items_rc.borrow_mut().get_mut("root").unwrap().clone();
-        let mut root = (*root_rc).borrow_mut();
-        let chain1 = chain.get(1).unwrap().clone();
+    /// Every monkey `root` depends on, each one after the monkeys it waits for.
+    fn topological_order(&self, root: usize) -> Result<Vec<usize>, SolveError> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum Visit {
+            New,
+            InProgress,
+            Done,
+        }
 
-        let to_match = if root.name1 == (*chain1).borrow().name {
-            get_number(&root.name2, items_rc.clone())
-        } else {
-            get_number(&root.name1, items_rc.clone())
-        };
-        root.should_be = to_match * 2;
+        let mut visits = vec![Visit::New; self.jobs.len()];
+        let mut order = Vec::new();
+        let mut stack = vec![(root, false)];
+        while let Some((id, expanded)) = stack.pop() {
+            if expanded {
+                visits[id] = Visit::Done;
+                order.push(id);
+                continue;
+            }
+            if visits[id] != Visit::New {
+                continue;
+            }
+
+            visits[id] = Visit::InProgress;
+            stack.push((id, true));
+            if let Job::Operation(id1, _, id2) = self.jobs[id] {
+                for operand in [id2, id1] {
+                    match visits[operand] {
+                        Visit::InProgress => {
+                            return Err(SolveError::Cycle(self.names[operand].clone()))
+                        }
+                        Visit::New => stack.push((operand, false)),
+                        Visit::Done => {}
+                    }
+                }
+            }
+        }
+        Ok(order)
     }
 
-    // Solve
-    for i in 0..chain.len() - 1 {
-        let chl = chain.get(i).unwrap().clone();
-        let next = chain.get(i + 1).unwrap().clone();
+    fn evaluate(&self, root: usize) -> Result<i64, SolveError> {
+        let mut values = vec![0; self.jobs.len()];
+        for id in self.topological_order(root)? {
+            values[id] = match self.jobs[id] {
+                Job::Number(number) => number,
+                Job::Operation(id1, operator, id2) => {
+                    let (a, b) = (values[id1], values[id2]);
+                    match operator {
+                        Operator::Add => a.checked_add(b).ok_or(SolveError::Overflow)?,
+                        Operator::Sub => a.checked_sub(b).ok_or(SolveError::Overflow)?,
+                        Operator::Mul => a.checked_mul(b).ok_or(SolveError::Overflow)?,
+                        Operator::Div if b == 0 => return Err(SolveError::DivisionByZero),
+                        Operator::Div => a.checked_div(b).ok_or(SolveError::Overflow)?,
+                    }
+                }
+            };
+        }
+        Ok(values[root])
+    }
 
-        let given = if (*chl).borrow().name1 != (*next).borrow().name {
-            (*chl).borrow().name1.clone()
-        } else {
-            (*chl).borrow().name2.clone()
+    /// Linear form of the monkey `root` in the monkey `variable`.
+    fn linear_form(&self, root: usize, variable: usize) -> Result<LinearForm, SolveError> {
+        let zero = Rational::from_integer(0);
+        let mut forms = vec![(zero, zero); self.jobs.len()];
+        for id in self.topological_order(root)? {
+            forms[id] = if id == variable {
+                (Rational::from_integer(1), zero)
+            } else {
+                match self.jobs[id] {
+                    Job::Number(number) => (zero, Rational::from_integer(number as i128)),
+                    Job::Operation(id1, operator, id2) => {
+                        combine_linear(forms[id1], operator, forms[id2])?
+                    }
+                }
+            };
+        }
+        Ok(forms[root])
+    }
+
+    /// Expression of the monkey `root` with the monkey `variable` left unknown,
+    /// every subtree without the variable is folded into a constant.
+    fn build_expr(&self, root: usize, variable: usize) -> Result<Expr, SolveError> {
+        let order = self.topological_order(root)?;
+        // Subexpressions are moved into their parent, only shared ones are copied
+        let mut uses = vec![0; self.jobs.len()];
+        for id in &order {
+            if let Job::Operation(id1, _, id2) = self.jobs[*id] {
+                if *id != variable {
+                    uses[id1] += 1;
+                    uses[id2] += 1;
+                }
+            }
+        }
+        let mut exprs: Vec<Option<Expr>> = (0..self.jobs.len()).map(|_| None).collect();
+        let mut operand = |exprs: &mut Vec<Option<Expr>>, id: usize| {
+            uses[id] -= 1;
+            if uses[id] == 0 {
+                exprs[id].take().unwrap()
+            } else {
+                exprs[id].clone().unwrap()
+            }
         };
+        for id in order {
+            let expr = if id == variable {
+                Expr::Variable(self.names[id].clone())
+            } else {
+                match self.jobs[id] {
+                    Job::Number(number) => Expr::Constant(Rational::from_integer(number as i128)),
+                    Job::Operation(id1, operator, id2) => {
+                        let left = operand(&mut exprs, id1);
+                        let right = operand(&mut exprs, id2);
+                        match (&left, &right) {
+                            (Expr::Constant(a), Expr::Constant(b)) => {
+                                Expr::Constant(a.apply(operator, *b)?)
+                            }
+                            _ => Expr::Binary(Box::new(left), operator, Box::new(right)),
+                        }
+                    }
+                }
+            };
+            exprs[id] = Some(expr);
+        }
+        Ok(exprs[root].take().unwrap())
+    }
+}
 
-        let is_left = (*chl).borrow().name1 == (*next).borrow().name;
+fn run(input_file: &str) -> i64 {
+    // Parse
+    let input = fs::read_to_string(input_file).unwrap();
+    let monkeys = Monkeys::parse(&input).unwrap_or_else(|error| panic!("{}", error));
 
-        (*next).borrow_mut().should_be = recalculate(chl.clone(), given, is_left, items_rc.clone());
-        // println!("{:?}", (*next).borrow());
-    }
+    // Solve
+    let result = monkeys
+        .id("root")
+        .and_then(|root| monkeys.evaluate(root))
+        .unwrap_or_else(|error| panic!("Could not evaluate root: {}", error));
+    println!("Result: {}", result);
+    result
+}
+
+fn solve_for(monkeys: &Monkeys, variable: &str) -> Result<Rational, SolveError> {
+    let Job::Operation(id1, _, id2) = monkeys.jobs[monkeys.id("root")?] else {
+        panic!("The root monkey has to compare two other monkeys");
+    };
+    let variable = monkeys.id(variable)?;
+
+    let left = monkeys.linear_form(id1, variable)?;
+    let right = monkeys.linear_form(id2, variable)?;
+    println!(
+        "Equation: {} = {}",
+        monkeys.build_expr(id1, variable)?,
+        monkeys.build_expr(id2, variable)?
+    );
+
+    solve_equation(left, right)
+}
 
-    let human_rc = (*items_rc).borrow().get("humn").unwrap().clone();
-    let human = (*human_rc).borrow();
-    println!("Result is {}", human.should_be);
+fn run2(input_file: &str) -> Rational {
+    let input = fs::read_to_string(input_file).unwrap();
+    match Monkeys::parse(&input).and_then(|monkeys| solve_for(&monkeys, "humn")) {
+        Ok(result) => {
+            println!("Result is {}", result);
+            result
+        }
+        Err(error) => panic!("Could not solve for humn: {}", error),
+    }
 }
 
 fn main() {
@@ -257,16 +467,100 @@ mod main_test {
 
     use crate::run;
     use crate::run2;
+    use crate::solve_for;
+    use crate::Monkeys;
+    use crate::Rational;
+    use crate::SolveError;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 152);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        assert_eq!(
+            run2(input_path.to_str().unwrap()),
+            Rational::from_integer(301)
+        );
+    }
+
+    #[test]
+    fn test_solve_with_variable_on_both_sides() {
+        let monkeys = Monkeys::parse(
+            "root: left + rght\nleft: humn / thre\nthre: 3\nrght: humn - five\nfive: 5\nhumn: 1",
+        )
+        .unwrap();
+        assert_eq!(solve_for(&monkeys, "humn"), Ok(Rational::new(15, 2)));
+        assert_eq!(solve_for(&monkeys, "thre"), Err(SolveError::NonLinear));
+    }
+
+    #[test]
+    fn test_overflow_is_an_error() {
+        // (9 * 10^18)^4 is beyond an i128
+        let monkeys = Monkeys::parse(
+            "root: aaaa + bbbb\naaaa: humn * cccc\ncccc: dddd * dddd\n\
+             dddd: eeee * eeee\neeee: 9000000000000000000\nbbbb: 1\nhumn: 5",
+        )
+        .unwrap();
+        assert_eq!(solve_for(&monkeys, "humn"), Err(SolveError::Overflow));
+        assert_eq!(
+            monkeys.evaluate(monkeys.id("root").unwrap()),
+            Err(SolveError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_cycles_and_deep_chains() {
+        let monkeys =
+            Monkeys::parse("root: aaaa + bbbb\naaaa: bbbb * cccc\nbbbb: 2\ncccc: aaaa - bbbb")
+                .unwrap();
+        assert_eq!(
+            monkeys.evaluate(monkeys.id("root").unwrap()),
+            Err(SolveError::Cycle(String::from("aaaa")))
+        );
+
+        // Far deeper than a recursive evaluation could go
+        let depth = 200_000;
+        let mut lines = vec![String::from("root: m0 + one"), String::from("one: 1")];
+        for i in 0..depth {
+            lines.push(format!("m{}: m{} + one", i, i + 1));
+        }
+        lines.push(format!("m{}: 0", depth));
+        let monkeys = Monkeys::parse(&lines.join("\n")).unwrap();
+        assert_eq!(
+            monkeys.evaluate(monkeys.id("root").unwrap()),
+            Ok(depth as i64 + 1)
+        );
+
+        // The same depth on the path to humn, 2 * humn + depth = 7
+        let mut lines = vec![
+            String::from("root: m0 + goal"),
+            String::from("goal: 7"),
+            String::from("one: 1"),
+            String::from("two: 2"),
+        ];
+        for i in 0..depth {
+            lines.push(format!("m{}: m{} + one", i, i + 1));
+        }
+        lines.push(format!("m{}: humn * two", depth));
+        lines.push(String::from("humn: 5"));
+        let monkeys = Monkeys::parse(&lines.join("\n")).unwrap();
+        assert_eq!(
+            solve_for(&monkeys, "humn"),
+            Ok(Rational::new(7 - depth as i128, 2))
+        );
+        let expr = monkeys
+            .build_expr(monkeys.id("m0").unwrap(), monkeys.id("humn").unwrap())
+            .unwrap();
+        let written = expr.to_string();
+        assert!(
+            written
+                .trim_start_matches('(')
+                .starts_with("humn * 2) + 1) + 1)")
+                && written.ends_with(" + 1)")
+        );
     }
 }
@@ -1,10 +1,9 @@
 use std::collections::{HashMap, HashSet};
-use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::fs;
 
 use utils::get_input_path;
 
-#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 struct Coord {
     pub x: i16,
     pub y: i16,
@@ -114,170 +113,433 @@ fn get_directional_check_coords(elf: &Coord, current_direction: &Direction) -> [
     }
 }
 
-fn run(input_file: &str) {
-    // Preamble
-    let mut elves: HashSet<Coord> = HashSet::new();
+/// One round of the hash set engine, returns whether any elf moved.
+fn step_hash_set(elves: &mut HashSet<Coord>, round: usize) -> bool {
     let direction_list = get_direction_array();
-
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
-
-    for (y, line) in reader.lines().enumerate() {
-        let line = line.unwrap().trim().to_string();
-        for (x, c) in line.chars().enumerate() {
-            if c == '#' {
-                elves.insert(Coord {
-                    x: x as i16,
-                    y: y as i16,
-                });
+    let mut moves: HashMap<Coord, Coord> = HashMap::new();
+    let mut seen: HashSet<Coord> = HashSet::new();
+    for elf in elves.iter() {
+        let wants_to_move = wants_to_move(elf, elves);
+
+        if wants_to_move {
+            for dc in 0..direction_list.len() {
+                let direction = (round + dc) % direction_list.len();
+                let current_direction = direction_list.get(direction).unwrap();
+                let coords_to_check = get_directional_check_coords(elf, current_direction);
+
+                if are_coords_empty(&coords_to_check, elves) {
+                    let move_to = coords_to_check.get(1).unwrap();
+                    if !seen.contains(move_to) && moves.insert(*move_to, *elf).is_some() {
+                        moves.remove(move_to);
+                        seen.insert(*move_to);
+                    }
+                    break;
+                }
             }
         }
     }
 
-    // Solve
-    const ROUNDS: usize = 10;
+    if moves.is_empty() {
+        return false;
+    }
 
-    for round in 0..ROUNDS {
-        //  println!("Starting round {}", round + 1);
-        let mut moves: HashMap<Coord, Coord> = HashMap::new();
-        let mut seen: HashSet<Coord> = HashSet::new();
-        for elf in &elves {
-            let wants_to_move = wants_to_move(elf, &elves);
-
-            if wants_to_move {
-                for dc in 0..direction_list.len() {
-                    let direction = (round + dc) % direction_list.len();
-                    let current_direction = direction_list.get(direction).unwrap();
-                    let coords_to_check = get_directional_check_coords(elf, current_direction);
-
-                    if are_coords_empty(&coords_to_check, &elves) {
-                        let move_to = coords_to_check.get(1).unwrap();
-                        if !seen.contains(move_to) && moves.insert(*move_to, *elf).is_some() {
-                            moves.remove(move_to);
-                            seen.insert(*move_to);
-                        }
-                        break;
-                    }
+    for (key, value) in moves {
+        elves.remove(&value);
+        elves.insert(key);
+    }
+    true
+}
+
+/// Empty columns and rows kept around the elves whenever the grid is rebuilt.
+const GRID_MARGIN_WORDS: usize = 1;
+const GRID_MARGIN_ROWS: usize = 32;
+
+/// Elves as a dense grid, every row is a bitset of `words` u64 where bit `x`
+/// lives in word `x / 64`. `origin` is the coordinate of bit 0 in row 0.
+struct BitGrid {
+    words: usize,
+    height: usize,
+    origin: Coord,
+    rows: Vec<u64>,
+}
+
+/// Bit `x` of the result is bit `x - 1` of `row`.
+fn shift_east(row: &[u64], word: usize) -> u64 {
+    let carry = if word > 0 { row[word - 1] >> 63 } else { 0 };
+    (row[word] << 1) | carry
+}
+
+/// Bit `x` of the result is bit `x + 1` of `row`.
+fn shift_west(row: &[u64], word: usize) -> u64 {
+    let carry = if word + 1 < row.len() {
+        row[word + 1] << 63
+    } else {
+        0
+    };
+    (row[word] >> 1) | carry
+}
+
+impl BitGrid {
+    fn from_coords(elves: &[Coord]) -> BitGrid {
+        let min_x = elves.iter().map(|elf| elf.x).min().unwrap_or(0);
+        let max_x = elves.iter().map(|elf| elf.x).max().unwrap_or(0);
+        let min_y = elves.iter().map(|elf| elf.y).min().unwrap_or(0);
+        let max_y = elves.iter().map(|elf| elf.y).max().unwrap_or(0);
+
+        let words = (max_x - min_x) as usize / 64 + 1 + 2 * GRID_MARGIN_WORDS;
+        let height = (max_y - min_y) as usize + 1 + 2 * GRID_MARGIN_ROWS;
+        let origin = Coord {
+            x: min_x - (GRID_MARGIN_WORDS * 64) as i16,
+            y: min_y - GRID_MARGIN_ROWS as i16,
+        };
+
+        let mut grid = BitGrid {
+            words,
+            height,
+            origin,
+            rows: vec![0; words * height],
+        };
+        for elf in elves {
+            let x = (elf.x - origin.x) as usize;
+            let y = (elf.y - origin.y) as usize;
+            grid.rows[y * words + x / 64] |= 1 << (x % 64);
+        }
+        grid
+    }
+
+    fn coords(&self) -> Vec<Coord> {
+        let mut elves = Vec::new();
+        for y in 0..self.height {
+            for word in 0..self.words {
+                let mut bits = self.rows[y * self.words + word];
+                while bits != 0 {
+                    let x = word * 64 + bits.trailing_zeros() as usize;
+                    elves.push(Coord {
+                        x: self.origin.x + x as i16,
+                        y: self.origin.y + y as i16,
+                    });
+                    bits &= bits - 1;
                 }
             }
         }
+        elves
+    }
 
-        for (key, value) in moves {
-            elves.remove(&value);
-            elves.insert(key);
+    fn row<'a>(rows: &'a [u64], words: usize, y: isize, empty: &'a [u64]) -> &'a [u64] {
+        if y < 0 || y as usize * words >= rows.len() {
+            empty
+        } else {
+            &rows[y as usize * words..(y as usize + 1) * words]
         }
     }
 
-    // Result
-    let mut min_x = i16::MAX;
-    let mut min_y = i16::MAX;
-    let mut max_x = i16::MIN;
-    let mut max_y = i16::MIN;
+    /// An elf on the outermost cells could leave the grid this round.
+    fn touches_border(&self) -> bool {
+        let last = self.words * (self.height - 1);
+        self.rows[..self.words].iter().any(|&bits| bits != 0)
+            || self.rows[last..].iter().any(|&bits| bits != 0)
+            || (0..self.height).any(|y| {
+                self.rows[y * self.words] & 1 != 0 || self.rows[(y + 1) * self.words - 1] >> 63 != 0
+            })
+    }
 
-    for elv in &elves {
-        min_x = min_x.min(elv.x);
-        min_y = min_y.min(elv.y);
-        max_x = max_x.max(elv.x);
-        max_y = max_y.max(elv.y);
+    /// One round for all elves at once, returns whether any elf moved.
+    fn step(&mut self, round: usize) -> bool {
+        if self.touches_border() {
+            *self = BitGrid::from_coords(&self.coords());
+        }
+
+        let words = self.words;
+        let empty = vec![0; words];
+        let size = self.rows.len();
+        // Proposals in the order of get_direction_array: north, south, west, east
+        let mut proposals = [vec![0; size], vec![0; size], vec![0; size], vec![0; size]];
+
+        for y in 0..self.height {
+            let north = Self::row(&self.rows, words, y as isize - 1, &empty);
+            let current = Self::row(&self.rows, words, y as isize, &empty);
+            let south = Self::row(&self.rows, words, y as isize + 1, &empty);
+            for word in 0..words {
+                if current[word] == 0 {
+                    continue;
+                }
+                let north_free = !(north[word] | shift_east(north, word) | shift_west(north, word));
+                let south_free = !(south[word] | shift_east(south, word) | shift_west(south, word));
+                let west_free = !(shift_east(north, word)
+                    | shift_east(current, word)
+                    | shift_east(south, word));
+                let east_free = !(shift_west(north, word)
+                    | shift_west(current, word)
+                    | shift_west(south, word));
+                let free = [north_free, south_free, west_free, east_free];
+
+                let mut undecided =
+                    current[word] & !(north_free & south_free & west_free & east_free);
+                for dc in 0..4 {
+                    let direction = (round + dc) % 4;
+                    let proposing = undecided & free[direction];
+                    proposals[direction][y * words + word] = proposing;
+                    undecided &= !proposing;
+                }
+            }
+        }
+
+        // Only elves proposing opposite directions can collide, they are two
+        // cells apart and both moves are cancelled
+        let [north, south, west, east] = &proposals;
+        let mut moved = false;
+        let mut rows = vec![0; size];
+        let mut west_ok = vec![0; words];
+        let mut east_ok = vec![0; words];
+        for y in 0..self.height {
+            let y = y as isize;
+            let north_here = Self::row(north, words, y, &empty);
+            let north_below = Self::row(north, words, y + 1, &empty);
+            let north_two_below = Self::row(north, words, y + 2, &empty);
+            let south_here = Self::row(south, words, y, &empty);
+            let south_above = Self::row(south, words, y - 1, &empty);
+            let south_two_above = Self::row(south, words, y - 2, &empty);
+            let west_here = Self::row(west, words, y, &empty);
+            let east_here = Self::row(east, words, y, &empty);
+            for word in 0..words {
+                let east_in_two = (east_here[word] << 2)
+                    | if word > 0 {
+                        east_here[word - 1] >> 62
+                    } else {
+                        0
+                    };
+                let west_in_two = (west_here[word] >> 2)
+                    | if word + 1 < words {
+                        west_here[word + 1] << 62
+                    } else {
+                        0
+                    };
+                west_ok[word] = west_here[word] & !east_in_two;
+                east_ok[word] = east_here[word] & !west_in_two;
+            }
+
+            for word in 0..words {
+                let north_ok = north_here[word] & !south_two_above[word];
+                let south_ok = south_here[word] & !north_two_below[word];
+                let from_south = north_below[word] & !south_above[word];
+                let from_north = south_above[word] & !north_below[word];
+
+                let leaving = north_ok | south_ok | west_ok[word] | east_ok[word];
+                moved |= leaving != 0;
+                rows[y as usize * words + word] = (self.rows[y as usize * words + word] & !leaving)
+                    | from_south
+                    | from_north
+                    | shift_west(&west_ok, word)
+                    | shift_east(&east_ok, word);
+            }
+        }
+
+        self.rows = rows;
+        moved
     }
+}
 
-    let result = (max_x - min_x + 1) * (max_y - min_y + 1) - elves.len() as i16;
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Engine {
+    HashSet,
+    Bitset,
+}
 
-    println!("The Result for part 1 is {result}");
+struct Options {
+    engine: Engine,
 }
 
-fn run2(input_file: &str) {
-    // Preamble
-    let mut elves: HashSet<Coord> = HashSet::new();
-    let direction_list = get_direction_array();
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            engine: Engine::Bitset,
+        }
+    }
+}
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        for arg in std::env::args().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                "--engine" => {
+                    options.engine = match value {
+                        "hash-set" => Engine::HashSet,
+                        "bitset" => Engine::Bitset,
+                        _ => panic!("Unknown engine {}", value),
+                    }
+                }
+                _ => panic!("Unknown argument {}", arg),
+            }
+        }
+        options
+    }
+}
 
-    for (y, line) in reader.lines().enumerate() {
-        let line = line.unwrap().trim().to_string();
+/// Positions of the elves after the simulation stopped.
+struct Simulation {
+    elves: Vec<Coord>,
+    /// First round in which no elf moved, if it was reached
+    settled_at: Option<usize>,
+}
+
+fn parse_elves(input: &str) -> Vec<Coord> {
+    let mut elves = Vec::new();
+    for (y, line) in input.lines().enumerate() {
         for (x, c) in line.chars().enumerate() {
             if c == '#' {
-                elves.insert(Coord {
+                elves.push(Coord {
                     x: x as i16,
                     y: y as i16,
                 });
             }
         }
     }
+    elves
+}
 
-    // Solve
-    const ROUNDS: usize = usize::MAX;
-    let mut end_at = 0;
-
-    for round in 0..ROUNDS {
-        // println!("Starting round {}", round + 1);
-        let mut moves: HashMap<Coord, Coord> = HashMap::new();
-        let mut seen: HashSet<Coord> = HashSet::new();
-        for elf in &elves {
-            let wants_to_move = wants_to_move(elf, &elves);
-
-            if wants_to_move {
-                for dc in 0..direction_list.len() {
-                    let direction = (round + dc) % direction_list.len();
-                    let current_direction = direction_list.get(direction).unwrap();
-                    let coords_to_check = get_directional_check_coords(elf, current_direction);
-
-                    if are_coords_empty(&coords_to_check, &elves) {
-                        let move_to = coords_to_check.get(1).unwrap();
-                        if !seen.contains(move_to) && moves.insert(*move_to, *elf).is_some() {
-                            moves.remove(move_to);
-                            seen.insert(*move_to);
-                        }
-                        break;
-                    }
+/// Runs at most `max_rounds` rounds, stopping early once no elf moves.
+fn simulate(elves: &[Coord], engine: Engine, max_rounds: usize) -> Simulation {
+    let mut settled_at = None;
+    let elves = match engine {
+        Engine::HashSet => {
+            let mut elves: HashSet<Coord> = elves.iter().copied().collect();
+            for round in 0..max_rounds {
+                if !step_hash_set(&mut elves, round) {
+                    settled_at = Some(round + 1);
+                    break;
                 }
             }
+            elves.into_iter().collect()
         }
-
-        if moves.is_empty() {
-            end_at = round + 1;
-            break;
+        Engine::Bitset => {
+            let mut grid = BitGrid::from_coords(elves);
+            for round in 0..max_rounds {
+                if !grid.step(round) {
+                    settled_at = Some(round + 1);
+                    break;
+                }
+            }
+            grid.coords()
         }
+    };
+    Simulation { elves, settled_at }
+}
 
-        for (key, value) in moves {
-            elves.remove(&value);
-            elves.insert(key);
-        }
+fn empty_ground(elves: &[Coord]) -> usize {
+    let mut min_x = i16::MAX;
+    let mut min_y = i16::MAX;
+    let mut max_x = i16::MIN;
+    let mut max_y = i16::MIN;
+
+    for elv in elves {
+        min_x = min_x.min(elv.x);
+        min_y = min_y.min(elv.y);
+        max_x = max_x.max(elv.x);
+        max_y = max_y.max(elv.y);
     }
 
+    (max_x - min_x + 1) as usize * (max_y - min_y + 1) as usize - elves.len()
+}
+
+fn run(input_file: &str, options: &Options) -> usize {
+    // Parse
+    let elves = parse_elves(&fs::read_to_string(input_file).unwrap());
+
+    // Solve
+    const ROUNDS: usize = 10;
+    let simulation = simulate(&elves, options.engine, ROUNDS);
+
+    // Result
+    let result = empty_ground(&simulation.elves);
+    println!("The Result for part 1 is {result}");
+    result
+}
+
+fn run2(input_file: &str, options: &Options) -> usize {
+    // Parse
+    let elves = parse_elves(&fs::read_to_string(input_file).unwrap());
+
+    // Solve
+    let simulation = simulate(&elves, options.engine, usize::MAX);
+    let end_at = simulation.settled_at.unwrap();
+
     // Result
     println!("It took {end_at} rounds");
+    end_at
 }
 
 fn main() {
     let input_path = get_input_path(file!());
     let input_file = input_path.to_str().unwrap();
+    let options = Options::from_args();
 
     println!("{input_file:?}");
 
-    run(input_file);
-    run2(input_file);
+    run(input_file, &options);
+    run2(input_file, &options);
 }
 
 #[cfg(test)]
 mod main_test {
     use utils::get_test_input_path;
 
+    use crate::parse_elves;
     use crate::run;
     use crate::run2;
+    use crate::simulate;
+    use crate::Engine;
+    use crate::Options;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        for engine in [Engine::HashSet, Engine::Bitset] {
+            assert_eq!(run(input_path.to_str().unwrap(), &Options { engine }), 110);
+        }
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        for engine in [Engine::HashSet, Engine::Bitset] {
+            assert_eq!(run2(input_path.to_str().unwrap(), &Options { engine }), 20);
+        }
+    }
+
+    #[test]
+    fn test_engines_agree_on_every_round() {
+        // A scattered 70x70 field, wide enough to span several bitset words
+        let mut seed: u64 = 23;
+        let mut lines = Vec::new();
+        for _ in 0..70 {
+            let line: String = (0..70)
+                .map(|_| {
+                    seed = seed
+                        .wrapping_mul(6364136223846793005)
+                        .wrapping_add(1442695040888963407);
+                    if (seed >> 33) % 3 == 1 {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            lines.push(line);
+        }
+        let elves = parse_elves(&lines.join("\n"));
+
+        for rounds in [1, 2, 3, 4, 5, 10, 50] {
+            let mut hash_set = simulate(&elves, Engine::HashSet, rounds).elves;
+            let mut bitset = simulate(&elves, Engine::Bitset, rounds).elves;
+            hash_set.sort();
+            bitset.sort();
+            assert_eq!(hash_set, bitset, "Engines differ after {rounds} rounds");
+        }
+        assert_eq!(
+            simulate(&elves, Engine::HashSet, usize::MAX).settled_at,
+            simulate(&elves, Engine::Bitset, usize::MAX).settled_at
+        );
     }
 }
@@ -1,30 +1,14 @@
-use std::collections::{HashSet, VecDeque};
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 
 use utils::get_input_path;
 
-#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+/// Position on the map including the walls, the inner valley starts at (1, 1).
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy, Debug)]
 struct Coord {
-    pub x: i64,
-    pub y: i64,
-}
-
-impl Coord {
-    pub fn distance(&self, other: &Self) -> i64 {
-        (self.x - other.x).abs() + (self.y - other.y).abs()
-    }
-
-    fn in_bounds(&self, dims: &Dims) -> bool {
-        self.x >= dims.x_min && self.x < dims.x_max && self.y >= dims.y_min && self.y < dims.y_max
-    }
-}
-
-struct Dims {
-    pub x_min: i64,
-    pub y_min: i64,
-    pub x_max: i64,
-    pub y_max: i64,
+    pub x: usize,
+    pub y: usize,
 }
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
@@ -46,423 +30,435 @@ impl Orientation {
     }
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-struct Blizzard {
-    pub coord: Coord,
-    pub orientation: Orientation,
+/// Fixed size bitset, bit `i` lives in word `i / 64`.
+#[derive(Clone)]
+struct Bitmask {
+    words: Vec<u64>,
 }
-impl Blizzard {
-    pub(crate) fn from_xyo(x: usize, y: usize, c: char) -> Self {
-        Self {
-            coord: Coord {
-                x: x as i64,
-                y: y as i64,
-            },
-            orientation: Orientation::from_char(c),
+
+impl Bitmask {
+    fn new(len: usize) -> Self {
+        Bitmask {
+            words: vec![0; len.div_ceil(64)],
         }
     }
-}
-
-#[derive(PartialEq, Eq, Clone, Copy, Hash)]
-struct Expedition {
-    pub coords: Coord,
-    pub minutes: usize,
-    pub distance: i64,
-}
 
-impl PartialOrd for Expedition {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        match self.coords.partial_cmp(&other.coords) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        match self.minutes.partial_cmp(&other.minutes) {
-            Some(core::cmp::Ordering::Equal) => {}
-            ord => return ord,
-        }
-        self.distance.partial_cmp(&other.distance)
+    fn set(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
     }
-}
 
-impl Ord for Expedition {
-    // fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-    //     if self.minutes != other.minutes {
-    //         other.minutes.cmp(&self.minutes)
-    //     } else {
-    //         other.distance.cmp(&self.distance)
-    //     }
-    // }
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self.minutes != other.minutes {
-            self.minutes.cmp(&other.minutes)
-        } else {
-            self.distance.cmp(&other.distance)
-        }
+    fn get(&self, idx: usize) -> bool {
+        self.words[idx / 64] >> (idx % 64) & 1 != 0
     }
 }
 
-#[allow(dead_code)]
-fn print_map(_expedition: &Expedition, next_blizzards: &HashSet<Coord>, dims: &Dims) {
-    for y in 0..dims.y_max {
-        for x in 0..dims.x_max {
-            if y == 0 || x == 0 {
-                print!("#");
-            }
-            if next_blizzards.contains(&Coord { x, y }) {
-                print!("X");
-            } else {
-                print!(".");
-            }
-        }
-        print!("#");
-        println!();
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
-    for _ in 0..dims.y_max + 3 {
-        print!("#");
-    }
-    println!();
 }
 
-fn get_next_positions(
-    base: &Coord,
-    dims: &Dims,
-    blizzard_coords: &HashSet<Coord>,
-    skip: &HashSet<Coord>,
-) -> Vec<Coord> {
-    let mut rtn = Vec::from([
-        Coord {
-            x: base.x + 1,
-            y: base.y,
-        },
-        Coord {
-            x: base.x - 1,
-            y: base.y,
-        },
-        Coord {
-            x: base.x,
-            y: base.y + 1,
-        },
-        Coord {
-            x: base.x,
-            y: base.y - 1,
-        },
-        Coord {
-            x: base.x,
-            y: base.y,
-        },
-    ]);
-
-    for idx in (0..rtn.len()).rev() {
-        if !skip.contains(&rtn[idx])
-            && (!rtn[idx].in_bounds(dims) || blizzard_coords.contains(&rtn[idx]))
-        {
-            rtn.remove(idx);
-        }
-    }
+fn lcm(a: usize, b: usize) -> usize {
+    a / gcd(a, b) * b
+}
 
-    rtn
+/// The blizzards of the valley as their positions at minute 0. A blizzard
+/// moving right which starts at inner column `x0` is at `x0 + t` (mod width)
+/// at minute `t`, so a cell is occupied at minute `t` when the masks have a
+/// bit at the cell shifted back by `t`. Everything repeats after `period`.
+struct Valley {
+    width: usize,
+    height: usize,
+    start: Coord,
+    end: Coord,
+    period: usize,
+    /// Per inner row, the columns of blizzards moving left and right
+    left: Vec<Bitmask>,
+    right: Vec<Bitmask>,
+    /// Per inner column, the rows of blizzards moving up and down
+    up: Vec<Bitmask>,
+    down: Vec<Bitmask>,
 }
 
-fn get_blizzard_positions<'a>(
-    blizzard_cache: &'a mut Vec<Vec<Blizzard>>,
-    blizzard_coords_cache: &'a mut Vec<HashSet<Coord>>,
-    minutes: usize,
-    dims: &'a Dims,
-) -> &'a HashSet<Coord> {
-    let range_start = blizzard_cache.len();
-    for _ in range_start..minutes + 1 {
-        let mut next_blizzard = (*blizzard_cache.last().unwrap()).clone();
-        for next in &mut next_blizzard {
-            match next.orientation {
-                Orientation::Up => {
-                    next.coord.y -= 1;
-                    if dims.y_min > next.coord.y {
-                        next.coord.y = dims.y_max - 1;
-                    }
-                }
-                Orientation::Down => {
-                    next.coord.y += 1;
-                    if dims.y_max <= next.coord.y {
-                        next.coord.y = dims.y_min;
-                    }
-                }
-                Orientation::Left => {
-                    next.coord.x -= 1;
-                    if dims.x_min > next.coord.x {
-                        next.coord.x = dims.x_max - 1;
-                    }
+impl Valley {
+    fn parse(input_file: &str) -> Valley {
+        let file = File::open(input_file).unwrap();
+        let reader = BufReader::new(file);
+
+        let lines: Vec<String> = reader
+            .lines()
+            .map(|line| line.unwrap().trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect();
+
+        let width = lines[0].len() - 2;
+        let height = lines.len() - 2;
+        let opening = |line: &str| line.chars().position(|c| c == '.').unwrap();
+        let start = Coord {
+            x: opening(&lines[0]),
+            y: 0,
+        };
+        let end = Coord {
+            x: opening(lines.last().unwrap()),
+            y: height + 1,
+        };
+
+        let mut left = vec![Bitmask::new(width); height];
+        let mut right = vec![Bitmask::new(width); height];
+        let mut up = vec![Bitmask::new(height); width];
+        let mut down = vec![Bitmask::new(height); width];
+        for (y, line) in lines[1..=height].iter().enumerate() {
+            for (x, c) in line.chars().skip(1).take(width).enumerate() {
+                if c == '.' {
+                    continue;
                 }
-                Orientation::Right => {
-                    next.coord.x += 1;
-                    if dims.x_max <= next.coord.x {
-                        next.coord.x = dims.x_min
-                    }
+                match Orientation::from_char(c) {
+                    Orientation::Left => left[y].set(x),
+                    Orientation::Right => right[y].set(x),
+                    Orientation::Up => up[x].set(y),
+                    Orientation::Down => down[x].set(y),
                 }
-            };
+            }
         }
 
-        let coords_collection: Vec<Coord> = next_blizzard.iter().map(|nb| nb.coord).collect();
-        let coords: HashSet<Coord> = HashSet::from_iter(coords_collection.into_iter());
-        blizzard_coords_cache.push(coords);
-        blizzard_cache.push(next_blizzard);
+        Valley {
+            width,
+            height,
+            start,
+            end,
+            period: lcm(width, height),
+            left,
+            right,
+            up,
+            down,
+        }
     }
 
-    blizzard_coords_cache.get(minutes).unwrap()
-}
-
-fn run(input_file: &str) {
-    // Preamble
-    let mut start: Coord = Coord { x: 0, y: 0 };
-    let mut blizzards: Vec<Blizzard> = Vec::new();
-
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
+    /// Whether the expedition may stand on `coord` at `minute`.
+    fn is_free(&self, coord: Coord, minute: usize) -> bool {
+        if coord == self.start || coord == self.end {
+            return true;
+        }
+        if coord.x == 0 || coord.x > self.width || coord.y == 0 || coord.y > self.height {
+            return false;
+        }
 
-    let mut end_y = 0;
-    let mut max_x: i64 = 0;
+        let (x, y) = (coord.x - 1, coord.y - 1);
+        let (shift_x, shift_y) = (minute % self.width, minute % self.height);
+        !(self.right[y].get((x + self.width - shift_x) % self.width)
+            || self.left[y].get((x + shift_x) % self.width)
+            || self.down[x].get((y + self.height - shift_y) % self.height)
+            || self.up[x].get((y + shift_y) % self.height))
+    }
 
-    let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+    /// Cells reachable from `coord` within one minute, including waiting.
+    fn neighbours(&self, coord: Coord) -> impl Iterator<Item = Coord> + '_ {
+        let candidates = [
+            Some(coord),
+            coord.y.checked_sub(1).map(|y| Coord { x: coord.x, y }),
+            Some(Coord {
+                x: coord.x,
+                y: coord.y + 1,
+            }),
+            coord.x.checked_sub(1).map(|x| Coord { x, y: coord.y }),
+            Some(Coord {
+                x: coord.x + 1,
+                y: coord.y,
+            }),
+        ];
+        candidates
+            .into_iter()
+            .flatten()
+            .filter(|next| next.x <= self.width + 1 && next.y <= self.height + 1)
+    }
 
-    for (y, line) in lines.iter().enumerate() {
-        end_y += 1;
+    fn state_index(&self, coord: Coord, minute: usize, stage: usize) -> usize {
+        (((stage * (self.height + 2) + coord.y) * (self.width + 2)) + coord.x) * self.period
+            + minute % self.period
+    }
 
-        let line = line.trim().to_string();
-        if y == 0 {
-            start.x = line.chars().position(|c| c == '.').unwrap() as i64;
-            max_x = line.len() as i64 - 1;
+    fn resolve(&self, waypoint: Waypoint) -> Coord {
+        match waypoint {
+            Waypoint::Start => self.start,
+            Waypoint::End => self.end,
+            Waypoint::Cell(coord) => coord,
         }
+    }
 
-        for (x, c) in line.chars().enumerate() {
-            if c == '#' {
-                continue;
-            }
-            if c != '.' {
-                blizzards.push(Blizzard::from_xyo(x, y, c));
-            }
+    /// Fastest trip visiting `waypoints` in order, leaving the first one at
+    /// `minute`. The search runs over (position, minute mod period, waypoints
+    /// reached) so it stays optimal for interior waypoints where the
+    /// expedition can not simply wait for a better departure.
+    fn plan(&self, waypoints: &[Coord], minute: usize) -> Option<Vec<Leg>> {
+        struct Node {
+            coord: Coord,
+            stage: usize,
+            parent: usize,
         }
-    }
 
-    let end_x = lines
-        .last()
-        .unwrap()
-        .trim()
-        .chars()
-        .position(|c| c == '.')
-        .unwrap() as i64;
-    let end = Coord {
-        x: end_x,
-        y: end_y - 1,
-    };
-    let skip = HashSet::from([start, end]);
-
-    // Prepare
-    let dims = Dims {
-        x_min: 1,
-        y_min: 1,
-        x_max: max_x,
-        y_max: lines.len() as i64 - 1,
-    };
-    let start_end_distance = start.distance(&end);
-    let mut blizzard_cache: Vec<Vec<Blizzard>> = Vec::new();
-    let mut blizzard_coords_cache: Vec<HashSet<Coord>> = Vec::new();
-
-    let coords_collection: Vec<Coord> = blizzards.iter().map(|nb| nb.coord).collect();
-    let coords: HashSet<Coord> = HashSet::from_iter(coords_collection.into_iter());
-    let mut expedition_cache = HashSet::new();
-    blizzard_coords_cache.push(coords);
-    blizzard_cache.push(blizzards);
-
-    let start_expedition = Expedition {
-        coords: start,
-        minutes: 0,
-        distance: start_end_distance,
-    };
-
-    let mut queue: VecDeque<Expedition> = VecDeque::new();
-    queue.push_back(start_expedition);
-    // Solve
-    let mut result: Option<Expedition> = None;
-
-    let mut last_hightes_minute = 0;
-    'outer: while !queue.is_empty() {
-        let expedition = queue.pop_front().unwrap();
-        let next_minute = expedition.minutes + 1;
-        if last_hightes_minute < next_minute {
-            last_hightes_minute = next_minute;
-            // print!("\r{next_minute}");
+        let first = *waypoints.first()?;
+        let mut stage = 1;
+        while stage < waypoints.len() && waypoints[stage] == first {
+            stage += 1;
         }
 
-        let next_blizzards = get_blizzard_positions(
-            &mut blizzard_cache,
-            &mut blizzard_coords_cache,
-            next_minute,
-            &dims,
-        );
+        if stage == waypoints.len() {
+            return Some(Vec::new());
+        }
 
-        // print_map(&expedition, &next_blizzards, &dims);
+        let mut seen = vec![false; self.state_index(Coord { x: 0, y: 0 }, 0, waypoints.len())];
+        let mut layers = vec![vec![Node {
+            coord: first,
+            stage,
+            parent: usize::MAX,
+        }]];
+        let mut arrival = minute;
+        let goal = 'search: loop {
+            let frontier = layers.last().unwrap();
+            if frontier.is_empty() {
+                return None;
+            }
+            arrival += 1;
 
-        let next_positions = get_next_positions(&expedition.coords, &dims, next_blizzards, &skip);
+            let mut next_frontier = Vec::new();
+            for (parent, node) in frontier.iter().enumerate() {
+                for next in self.neighbours(node.coord) {
+                    if !self.is_free(next, arrival) {
+                        continue;
+                    }
+                    let mut stage = node.stage;
+                    while stage < waypoints.len() && waypoints[stage] == next {
+                        stage += 1;
+                    }
+                    if stage == waypoints.len() {
+                        next_frontier.push(Node {
+                            coord: next,
+                            stage,
+                            parent,
+                        });
+                        let goal = next_frontier.len() - 1;
+                        layers.push(next_frontier);
+                        break 'search goal;
+                    }
 
-        for next_position in next_positions {
-            let distance = next_position.distance(&end);
-            if distance == 0 {
-                result = Some(expedition);
-                break 'outer;
+                    let idx = self.state_index(next, arrival, stage);
+                    if !seen[idx] {
+                        seen[idx] = true;
+                        next_frontier.push(Node {
+                            coord: next,
+                            stage,
+                            parent,
+                        });
+                    }
+                }
             }
-            let next_expedition = Expedition {
-                coords: next_position,
-                minutes: next_minute,
-                distance,
-            };
-            if expedition_cache.insert(next_expedition) {
-                queue.push_back(next_expedition);
+            layers.push(next_frontier);
+        };
+
+        // Walk the parents back to the first waypoint
+        let mut path = Vec::with_capacity(layers.len());
+        let mut idx = goal;
+        for layer in layers.iter().rev() {
+            let node = &layer[idx];
+            path.push((node.coord, node.stage));
+            idx = node.parent;
+        }
+        path.reverse();
+
+        let mut legs = Vec::new();
+        let mut leg = Leg {
+            from: first,
+            to: first,
+            departure: minute,
+            moves: Vec::new(),
+        };
+        for (step, window) in path.windows(2).enumerate() {
+            let ((from, from_stage), (to, to_stage)) = (window[0], window[1]);
+            leg.moves.push(Move::between(from, to));
+            if to_stage != from_stage {
+                leg.to = to;
+                let departure = minute + step + 1;
+                legs.push(std::mem::replace(
+                    &mut leg,
+                    Leg {
+                        from: to,
+                        to,
+                        departure,
+                        moves: Vec::new(),
+                    },
+                ));
             }
         }
-        queue.make_contiguous().sort_unstable_by(|a, b| a.cmp(b));
-        // queue.make_contiguous().sort_unstable();
+        Some(legs)
     }
-    println!();
-    // Result
-    let minutes = result.unwrap().minutes + 1;
-    println!("The expedition needs {minutes} minutes!");
 }
 
-fn run2(input_file: &str) {
-    // Preamble
-    let mut start: Coord = Coord { x: 0, y: 0 };
-    let mut blizzards: Vec<Blizzard> = Vec::new();
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Move {
+    Wait,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Move {
+    fn between(from: Coord, to: Coord) -> Move {
+        match (to.x as i64 - from.x as i64, to.y as i64 - from.y as i64) {
+            (0, 0) => Move::Wait,
+            (0, -1) => Move::Up,
+            (0, 1) => Move::Down,
+            (-1, 0) => Move::Left,
+            (1, 0) => Move::Right,
+            _ => panic!("{:?} and {:?} are not adjacent", from, to),
+        }
+    }
+}
 
-    // Parse
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Move::Wait => "wait",
+            Move::Up => "up",
+            Move::Down => "down",
+            Move::Left => "left",
+            Move::Right => "right",
+        };
+        write!(f, "{}", name)
+    }
+}
 
-    let mut end_y = 0;
-    let mut max_x: i64 = 0;
+/// One part of the itinerary, one move per minute from `departure` on.
+struct Leg {
+    from: Coord,
+    to: Coord,
+    departure: usize,
+    moves: Vec<Move>,
+}
 
-    let lines: Vec<String> = reader.lines().map(|l| l.unwrap()).collect();
+impl Leg {
+    fn minutes(&self) -> usize {
+        self.moves.len()
+    }
 
-    for (y, line) in lines.iter().enumerate() {
-        end_y += 1;
+    fn arrival(&self) -> usize {
+        self.departure + self.minutes()
+    }
+}
 
-        let line = line.trim().to_string();
-        if y == 0 {
-            start.x = line.chars().position(|c| c == '.').unwrap() as i64;
-            max_x = line.len() as i64 - 1;
-        }
+fn print_itinerary(legs: &[Leg]) {
+    for (idx, leg) in legs.iter().enumerate() {
+        println!(
+            "Leg {}: ({}, {}) -> ({}, {}) from minute {} to {}, {} minutes",
+            idx + 1,
+            leg.from.x,
+            leg.from.y,
+            leg.to.x,
+            leg.to.y,
+            leg.departure,
+            leg.arrival(),
+            leg.minutes()
+        );
+        let moves: Vec<String> = leg.moves.iter().map(Move::to_string).collect();
+        println!("    {}", moves.join(", "));
+    }
+}
 
-        for (x, c) in line.chars().enumerate() {
-            if c == '#' {
-                continue;
-            }
-            if c != '.' {
-                blizzards.push(Blizzard::from_xyo(x, y, c));
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Waypoint {
+    Start,
+    End,
+    Cell(Coord),
+}
+
+impl Waypoint {
+    fn parse(value: &str) -> Waypoint {
+        match value {
+            "start" => Waypoint::Start,
+            "end" => Waypoint::End,
+            _ => {
+                let (x, y) = value
+                    .split_once(',')
+                    .unwrap_or_else(|| panic!("Invalid waypoint {}", value));
+                Waypoint::Cell(Coord {
+                    x: x.parse().expect("Invalid waypoint x"),
+                    y: y.parse().expect("Invalid waypoint y"),
+                })
             }
         }
     }
+}
 
-    let end_x = lines
-        .last()
-        .unwrap()
-        .trim()
-        .chars()
-        .position(|c| c == '.')
-        .unwrap() as i64;
-    let end = Coord {
-        x: end_x,
-        y: end_y - 1,
-    };
-    let skip = HashSet::from([start, end]);
-
-    // Prepare
-    let dims = Dims {
-        x_min: 1,
-        y_min: 1,
-        x_max: max_x,
-        y_max: lines.len() as i64 - 1,
-    };
-    let start_end_distance = start.distance(&end);
-    let mut blizzard_cache: Vec<Vec<Blizzard>> = Vec::new();
-    let mut blizzard_coords_cache: Vec<HashSet<Coord>> = Vec::new();
-
-    let coords_collection: Vec<Coord> = blizzards.iter().map(|nb| nb.coord).collect();
-    let coords: HashSet<Coord> = HashSet::from_iter(coords_collection.into_iter());
-    let mut expedition_cache = HashSet::new();
-    blizzard_coords_cache.push(coords);
-    blizzard_cache.push(blizzards);
-
-    let mut queue: VecDeque<Expedition> = VecDeque::new();
-
-    // Solve
-    let mut result: Expedition = Expedition {
-        coords: start,
-        minutes: 0,
-        distance: start_end_distance,
-    };
-
-    let mut last_hightes_minute = 0;
+struct Options {
+    waypoints: Vec<Waypoint>,
+}
 
-    let end_points = [end, start, end];
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            waypoints: vec![
+                Waypoint::Start,
+                Waypoint::End,
+                Waypoint::Start,
+                Waypoint::End,
+            ],
+        }
+    }
+}
 
-    for (idx, end_point) in end_points.iter().enumerate() {
-        let start_expedition = Expedition {
-            coords: result.coords,
-            minutes: result.minutes,
-            distance: result.coords.distance(end_point),
-        };
-        queue.push_back(start_expedition);
-
-        'outer: while !queue.is_empty() {
-            let expedition = queue.pop_front().unwrap();
-            let next_minute = expedition.minutes + 1;
-            if last_hightes_minute < next_minute {
-                last_hightes_minute = next_minute;
-                // print!("\r{next_minute}");
+impl Options {
+    fn from_args() -> Self {
+        let mut options = Options::default();
+        for arg in std::env::args().skip(1) {
+            let (key, value) = arg.split_once('=').unwrap_or((&arg, ""));
+            match key {
+                // e.g. --waypoints=start;3,2;end
+                "--waypoints" => {
+                    options.waypoints = value.split(';').map(Waypoint::parse).collect()
+                }
+                _ => panic!("Unknown argument {}", arg),
             }
+        }
+        options
+    }
+}
 
-            let next_blizzards = get_blizzard_positions(
-                &mut blizzard_cache,
-                &mut blizzard_coords_cache,
-                next_minute,
-                &dims,
-            );
+fn run(input_file: &str) -> usize {
+    // Parse
+    let valley = Valley::parse(input_file);
 
-            // print_map(&expedition, &next_blizzards, &dims);
+    // Solve
+    let legs = valley
+        .plan(&[valley.start, valley.end], 0)
+        .expect("The end can not be reached");
 
-            let next_positions =
-                get_next_positions(&expedition.coords, &dims, next_blizzards, &skip);
+    // Result
+    let minutes = legs.last().unwrap().arrival();
+    println!("The expedition needs {minutes} minutes!");
+    minutes
+}
 
-            for next_position in next_positions {
-                let distance = next_position.distance(end_point);
+fn run2(input_file: &str, options: &Options) -> Vec<Leg> {
+    // Parse
+    let valley = Valley::parse(input_file);
 
-                let next_expedition = Expedition {
-                    coords: next_position,
-                    minutes: next_minute,
-                    distance,
-                };
-                if distance == 0 {
-                    queue.clear();
-                    expedition_cache.clear();
-                    result = next_expedition;
-                    break 'outer;
-                }
-                if expedition_cache.insert(next_expedition) {
-                    queue.push_back(next_expedition);
-                }
-            }
-            queue.make_contiguous().sort_unstable_by(|a, b| a.cmp(b));
-            // queue.make_contiguous().sort_unstable();
-        }
+    // Solve
+    let waypoints: Vec<Coord> = options
+        .waypoints
+        .iter()
+        .map(|waypoint| valley.resolve(*waypoint))
+        .collect();
+    let legs = valley
+        .plan(&waypoints, 0)
+        .expect("The waypoints can not be reached");
 
-        let stand = result.minutes;
-        println!(
-            "The expedition needed {stand} minutes after round {}!",
-            idx + 1
-        );
-    }
-    println!();
     // Result
-    println!("The expedition needs {} minutes!", result.minutes);
+    print_itinerary(&legs);
+    let minutes = legs.last().map_or(0, Leg::arrival);
+    println!("The expedition needs {minutes} minutes!");
+    legs
 }
 
 fn main() {
@@ -471,8 +467,10 @@ fn main() {
 
     println!("{input_file:?}");
 
+    let options = Options::from_args();
+
     run(input_file);
-    run2(input_file);
+    run2(input_file, &options);
 }
 
 #[cfg(test)]
@@ -481,16 +479,98 @@ mod main_test {
 
     use crate::run;
     use crate::run2;
+    use crate::Coord;
+    use crate::Leg;
+    use crate::Move;
+    use crate::Options;
+    use crate::Valley;
+    use crate::Waypoint;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        assert_eq!(run(input_path.to_str().unwrap()), 18);
     }
 
     #[test]
     fn test_input_part_2() {
         let input_path = get_test_input_path(file!());
-        run2(input_path.to_str().unwrap());
+        let legs = run2(input_path.to_str().unwrap(), &Options::default());
+        // The legs may split differently than 18, 23 and 13, the total is the same
+        assert_eq!(legs.len(), 3);
+        assert_eq!(legs.iter().map(Leg::minutes).sum::<usize>(), 54);
+        assert_eq!(legs.last().unwrap().arrival(), 54);
+    }
+
+    #[test]
+    fn test_occupancy_repeats_after_the_period() {
+        let input_path = get_test_input_path(file!());
+        let valley = Valley::parse(input_path.to_str().unwrap());
+        assert_eq!(valley.period, 12);
+
+        // Minute 1 of the puzzle description, row 1 is `#.>3.<.#`
+        let free: Vec<bool> = (1..=6)
+            .map(|x| valley.is_free(Coord { x, y: 1 }, 1))
+            .collect();
+        assert_eq!(free, [true, false, false, true, false, true]);
+
+        for minute in 0..valley.period {
+            for y in 0..valley.height + 2 {
+                for x in 0..valley.width + 2 {
+                    let coord = Coord { x, y };
+                    assert_eq!(
+                        valley.is_free(coord, minute),
+                        valley.is_free(coord, minute + valley.period)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_itinerary_through_interior_waypoints() {
+        let input_path = get_test_input_path(file!());
+        let input_file = input_path.to_str().unwrap();
+        let valley = Valley::parse(input_file);
+        let options = Options {
+            waypoints: ["start", "6,4", "1,1", "end"]
+                .into_iter()
+                .map(Waypoint::parse)
+                .collect(),
+        };
+        let legs = run2(input_file, &options);
+        assert_eq!(legs.len(), 3);
+
+        // Replaying the moves has to visit every waypoint without meeting a blizzard
+        let mut position = valley.start;
+        let mut minute = 0;
+        for (leg, waypoint) in legs.iter().zip(&options.waypoints[1..]) {
+            assert_eq!((leg.from, leg.departure), (position, minute));
+            for step in &leg.moves {
+                position = match step {
+                    Move::Wait => position,
+                    Move::Up => Coord {
+                        y: position.y - 1,
+                        ..position
+                    },
+                    Move::Down => Coord {
+                        y: position.y + 1,
+                        ..position
+                    },
+                    Move::Left => Coord {
+                        x: position.x - 1,
+                        ..position
+                    },
+                    Move::Right => Coord {
+                        x: position.x + 1,
+                        ..position
+                    },
+                };
+                minute += 1;
+                assert!(valley.is_free(position, minute));
+            }
+            assert_eq!(position, valley.resolve(*waypoint));
+            assert_eq!(leg.to, position);
+        }
     }
 }
@@ -1,58 +1,290 @@
+use std::cmp::Ordering;
+use std::fmt;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
+use std::iter::Sum;
+use std::ops::{Add, Mul, Neg, Sub};
+use std::str::FromStr;
 
 use utils::get_input_path;
+use utils::numeral::{NumeralError, NumeralSystem};
 
-fn run(input_file: &str) {
-    let mut total: i64 = 0;
+/// Balanced base 5 number of any size. `digits` are stored least significant
+/// first, each in `-2..=2`, without leading zeros, so zero has no digits.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+struct Snafu {
+    digits: Vec<i8>,
+}
 
-    let file = File::open(input_file).unwrap();
-    let reader = BufReader::new(file);
+impl Snafu {
+    /// Turns arbitrary per position values into balanced digits by carrying.
+    fn from_coefficients(coefficients: impl IntoIterator<Item = i128>) -> Snafu {
+        let mut digits = Vec::new();
+        let mut carry = 0;
+        let mut coefficients = coefficients.into_iter();
+        loop {
+            let value = match coefficients.next() {
+                Some(coefficient) => coefficient + carry,
+                None if carry != 0 => carry,
+                None => break,
+            };
+            let mut digit = value.rem_euclid(5);
+            if digit > 2 {
+                digit -= 5;
+            }
+            digits.push(digit as i8);
+            carry = (value - digit) / 5;
+        }
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Snafu { digits }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    /// The sign of a balanced number is the sign of its leading digit.
+    fn signum(&self) -> i8 {
+        self.digits.last().map_or(0, |digit| digit.signum())
+    }
+
+    /// The value, if it fits into an `i128`.
+    fn to_i128(&self) -> Option<i128> {
+        let mut value: i128 = 0;
+        for digit in self.digits.iter().rev() {
+            value = value.checked_mul(5)?.checked_add(*digit as i128)?;
+        }
+        Some(value)
+    }
+}
+
+impl From<i64> for Snafu {
+    fn from(value: i64) -> Self {
+        let mut digits: Vec<i8> = NumeralSystem::snafu()
+            .to_digits(value as i128)
+            .unwrap()
+            .into_iter()
+            .map(|digit| digit as i8)
+            .collect();
+        digits.reverse();
+        Snafu { digits }
+    }
+}
+
+impl FromStr for Snafu {
+    type Err = NumeralError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut digits: Vec<i8> = NumeralSystem::snafu()
+            .parse_digits(s)?
+            .into_iter()
+            .rev()
+            .map(|digit| digit as i8)
+            .collect();
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Ok(Snafu { digits })
+    }
+}
+
+impl fmt::Display for Snafu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let digits: Vec<i64> = self
+            .digits
+            .iter()
+            .rev()
+            .map(|digit| *digit as i64)
+            .collect();
+        let number = NumeralSystem::snafu()
+            .format_digits(&digits)
+            .map_err(|_| fmt::Error)?;
+        write!(f, "{}", number)
+    }
+}
+
+/// Digit by digit addition with a carry, without going through decimal.
+impl Add for Snafu {
+    type Output = Snafu;
+
+    fn add(self, other: Snafu) -> Snafu {
+        let len = self.digits.len().max(other.digits.len());
+        let mut digits = Vec::with_capacity(len + 1);
+        let mut carry = 0;
+        for idx in 0..len {
+            let a = self.digits.get(idx).copied().unwrap_or(0);
+            let b = other.digits.get(idx).copied().unwrap_or(0);
+            let mut digit = a + b + carry;
+            carry = 0;
+            if digit > 2 {
+                digit -= 5;
+                carry = 1;
+            } else if digit < -2 {
+                digit += 5;
+                carry = -1;
+            }
+            digits.push(digit);
+        }
+        if carry != 0 {
+            digits.push(carry);
+        }
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+        Snafu { digits }
+    }
+}
 
-    for (_index, line) in reader.lines().enumerate() {
-        let line = line.unwrap().trim().to_string();
-        let mut coef = 1;
-        for i in (0..line.len()).rev() {
-            let x = line.chars().nth(i).unwrap();
-            let n: i64 = "=-012".find(x).unwrap() as i64;
-            total += (n - 2) * coef;
-            coef = coef * 5;
+impl Neg for Snafu {
+    type Output = Snafu;
+
+    fn neg(self) -> Snafu {
+        Snafu {
+            digits: self.digits.into_iter().map(|digit| -digit).collect(),
         }
     }
+}
+
+impl Sub for Snafu {
+    type Output = Snafu;
+
+    fn sub(self, other: Snafu) -> Snafu {
+        self + -other
+    }
+}
 
-    let mut output = String::new();
+impl Mul for Snafu {
+    type Output = Snafu;
 
-    while total != 0 {
-        let rem = total % 5;
-        total = total / 5;
-        if rem <= 2 {
-            output = rem.to_string() + &output;
-        } else {
-            let nc = "   =-".chars().nth(rem as usize).unwrap();
-            output.insert(0, nc);
-            total += 1;
+    fn mul(self, other: Snafu) -> Snafu {
+        if self.is_zero() || other.is_zero() {
+            return Snafu::default();
         }
+        let mut coefficients = vec![0i128; self.digits.len() + other.digits.len()];
+        for (i, a) in self.digits.iter().enumerate() {
+            for (j, b) in other.digits.iter().enumerate() {
+                coefficients[i + j] += (*a as i128) * (*b as i128);
+            }
+        }
+        Snafu::from_coefficients(coefficients)
+    }
+}
+
+impl Ord for Snafu {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.clone() - other.clone()).signum().cmp(&0)
+    }
+}
+
+impl PartialOrd for Snafu {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Sum for Snafu {
+    fn sum<I: Iterator<Item = Snafu>>(iter: I) -> Snafu {
+        iter.fold(Snafu::default(), |total, number| total + number)
     }
+}
+
+fn run(input_file: &str) -> Snafu {
+    // Parse
+    let file = File::open(input_file).unwrap();
+    let reader = BufReader::new(file);
 
-    println!("The final Result is: {:?}", output);
+    let numbers: Vec<Snafu> = reader
+        .lines()
+        .map(|line| line.unwrap().trim().to_string())
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .unwrap_or_else(|error| panic!("Can not parse {}: {}", line, error))
+        })
+        .collect();
+
+    // Solve
+    let total: Snafu = numbers.into_iter().sum();
+
+    println!("The final Result is: {:?}", total.to_string());
+    if let Some(decimal) = total.to_i128() {
+        println!("In decimal that is {}", decimal);
+    }
+    total
 }
 
 fn main() {
     let input_path = get_input_path(file!());
     let input_file = input_path.to_str().unwrap();
 
-    run(&input_file);
+    run(input_file);
 }
 
 #[cfg(test)]
 mod main_test {
     use utils::get_test_input_path;
+    use utils::numeral::NumeralError;
 
     use crate::run;
+    use crate::Snafu;
 
     #[test]
     fn test_input_part_1() {
         let input_path = get_test_input_path(file!());
-        run(input_path.to_str().unwrap());
+        let total = run(input_path.to_str().unwrap());
+        assert_eq!(total.to_string(), "2=-1=0");
+        assert_eq!(total.to_i128(), Some(4890));
+    }
+
+    #[test]
+    fn test_arithmetic_matches_decimal() {
+        // Pseudo random operands, checked against i128 arithmetic
+        let mut seed: u64 = 25;
+        let mut next = || {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            (seed as i64) >> (seed % 62)
+        };
+        for _ in 0..2000 {
+            let (a, b) = (next(), next());
+            let (snafu_a, snafu_b) = (Snafu::from(a), Snafu::from(b));
+            let (a, b) = (a as i128, b as i128);
+
+            assert_eq!(snafu_a.to_string().parse::<Snafu>(), Ok(snafu_a.clone()));
+            assert_eq!(snafu_a.to_i128(), Some(a));
+            assert_eq!((snafu_a.clone() + snafu_b.clone()).to_i128(), Some(a + b));
+            assert_eq!((snafu_a.clone() - snafu_b.clone()).to_i128(), Some(a - b));
+            assert_eq!((snafu_a.clone() * snafu_b.clone()).to_i128(), Some(a * b));
+            assert_eq!((-snafu_a.clone()).to_i128(), Some(-a));
+            assert_eq!(snafu_a.cmp(&snafu_b), a.cmp(&b));
+        }
+    }
+
+    #[test]
+    fn test_precision_and_errors() {
+        // 5^60 is far beyond i64, 5^120 beyond i128
+        let big: Snafu = format!("1{}", "0".repeat(60)).parse().unwrap();
+        let square = big.clone() * big.clone();
+        assert_eq!(square.to_string(), format!("1{}", "0".repeat(120)));
+        assert_eq!(square.to_i128(), None);
+        assert_eq!((square.clone() - square.clone()).to_string(), "0");
+        assert!(square > big && -square < Snafu::from(i64::MIN));
+        assert_eq!(
+            [big.clone(), big.clone(), big].into_iter().sum::<Snafu>(),
+            format!("1{}", "0".repeat(59)).parse::<Snafu>().unwrap() * Snafu::from(15)
+        );
+
+        assert_eq!("".parse::<Snafu>(), Err(NumeralError::Empty));
+        assert_eq!(
+            "1=3-".parse::<Snafu>(),
+            Err(NumeralError::InvalidCharacter {
+                position: 2,
+                character: '3'
+            })
+        );
+        assert_eq!("00-".parse::<Snafu>(), Ok(Snafu::from(-1)));
     }
 }
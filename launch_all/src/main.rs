@@ -64,9 +64,17 @@ fn get_commands() -> Vec<String> {
 
 fn main() {
     let commands = get_commands();
+    // Day numbers given as arguments only benchmark those days, e.g. `launch_all 16`
+    let days: Vec<usize> = std::env::args()
+        .skip(1)
+        .map(|arg| arg.parse().expect("Expected a day number"))
+        .collect();
 
     let overall_start = Instant::now();
     for (idx, command) in commands.iter().enumerate() {
+        if !days.is_empty() && !days.contains(&(idx + 1)) {
+            continue;
+        }
         let start = Instant::now();
         unsafe {
             Command::new(command).output().unwrap_unchecked();
@@ -0,0 +1,187 @@
+/// Inclusive integer range `lo..=hi`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Interval {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Interval {
+    pub fn new(lo: i64, hi: i64) -> Self {
+        assert!(lo <= hi, "Interval {}..={} is empty", lo, hi);
+        Interval { lo, hi }
+    }
+
+    pub fn delta(&self) -> i64 {
+        self.hi - self.lo + 1
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    pub fn intersect(&self, other: &Interval) -> Option<Interval> {
+        let lo = self.lo.max(other.lo);
+        let hi = self.hi.min(other.hi);
+        (lo <= hi).then_some(Interval { lo, hi })
+    }
+}
+
+/// Set of integers stored as sorted, disjoint and non adjacent intervals.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntervalSet {
+    intervals: Vec<Interval>,
+}
+
+impl IntervalSet {
+    pub fn new() -> Self {
+        IntervalSet::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    /// Amount of integers in the set.
+    pub fn len(&self) -> i64 {
+        self.intervals.iter().map(Interval::delta).sum()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Interval> {
+        self.intervals.iter()
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        let idx = self
+            .intervals
+            .partition_point(|interval| interval.hi < value);
+        self.intervals
+            .get(idx)
+            .is_some_and(|interval| interval.contains(value))
+    }
+
+    /// Adds `lo..=hi`, merging it with every interval it overlaps or touches.
+    pub fn insert(&mut self, lo: i64, hi: i64) {
+        let mut merged = Interval::new(lo, hi);
+        let start = self
+            .intervals
+            .partition_point(|interval| interval.hi < merged.lo.saturating_sub(1));
+        let mut end = start;
+        while end < self.intervals.len() && self.intervals[end].lo <= merged.hi.saturating_add(1) {
+            merged.lo = merged.lo.min(self.intervals[end].lo);
+            merged.hi = merged.hi.max(self.intervals[end].hi);
+            end += 1;
+        }
+        self.intervals.splice(start..end, [merged]);
+    }
+
+    /// Removes `lo..=hi` from the set.
+    pub fn remove(&mut self, lo: i64, hi: i64) {
+        let removed = Interval::new(lo, hi);
+        let mut intervals = Vec::with_capacity(self.intervals.len() + 1);
+        for interval in &self.intervals {
+            if interval.intersect(&removed).is_none() {
+                intervals.push(*interval);
+                continue;
+            }
+            if interval.lo < removed.lo {
+                intervals.push(Interval::new(interval.lo, removed.lo - 1));
+            }
+            if interval.hi > removed.hi {
+                intervals.push(Interval::new(removed.hi + 1, interval.hi));
+            }
+        }
+        self.intervals = intervals;
+    }
+
+    pub fn union(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = self.clone();
+        for interval in &other.intervals {
+            result.insert(interval.lo, interval.hi);
+        }
+        result
+    }
+
+    pub fn subtract(&self, other: &IntervalSet) -> IntervalSet {
+        let mut result = self.clone();
+        for interval in &other.intervals {
+            result.remove(interval.lo, interval.hi);
+        }
+        result
+    }
+
+    pub fn intersect(&self, other: &IntervalSet) -> IntervalSet {
+        let mut intervals = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.intervals.len() && j < other.intervals.len() {
+            if let Some(interval) = self.intervals[i].intersect(&other.intervals[j]) {
+                intervals.push(interval);
+            }
+            if self.intervals[i].hi < other.intervals[j].hi {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { intervals }
+    }
+
+    /// Every integer in `lo..=hi` that is not part of the set.
+    pub fn gaps(&self, lo: i64, hi: i64) -> IntervalSet {
+        let mut bounds = IntervalSet::new();
+        bounds.insert(lo, hi);
+        bounds.subtract(self)
+    }
+}
+
+impl From<Interval> for IntervalSet {
+    fn from(interval: Interval) -> Self {
+        IntervalSet {
+            intervals: vec![interval],
+        }
+    }
+}
+
+impl FromIterator<Interval> for IntervalSet {
+    fn from_iter<T: IntoIterator<Item = Interval>>(iter: T) -> Self {
+        let mut set = IntervalSet::new();
+        for interval in iter {
+            set.insert(interval.lo, interval.hi);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::interval_set::{Interval, IntervalSet};
+
+    fn set(intervals: &[(i64, i64)]) -> IntervalSet {
+        intervals
+            .iter()
+            .map(|(lo, hi)| Interval::new(*lo, *hi))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_merges_overlapping_and_adjacent() {
+        let set = set(&[(5, 8), (0, 2), (3, 4), (7, 12), (20, 25)]);
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![Interval::new(0, 12), Interval::new(20, 25)]
+        );
+        assert_eq!(set.len(), 19);
+        assert!(set.contains(12));
+        assert!(!set.contains(13));
+    }
+
+    #[test]
+    fn test_subtract_intersect_and_gaps() {
+        let a = set(&[(0, 10), (20, 30)]);
+        let b = set(&[(5, 22), (28, 28)]);
+        assert_eq!(a.subtract(&b), set(&[(0, 4), (23, 27), (29, 30)]));
+        assert_eq!(a.intersect(&b), set(&[(5, 10), (20, 22), (28, 28)]));
+        assert_eq!(a.union(&b), set(&[(0, 30)]));
+        assert_eq!(a.gaps(-5, 25), set(&[(-5, -1), (11, 19)]));
+        assert!(a.union(&b).gaps(0, 30).is_empty());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod interval_set;
+pub mod numeral;
+
 use std::{
     env::current_dir,
     path::{Path, PathBuf},
@@ -44,7 +47,7 @@ pub fn get_test_input_2_path(src_path: &str) -> PathBuf {
             .join("input")
             .join("input_test_2.txt")
     } else {
-        current_dir().unwrap().join("input").join("input_test.txt")
+        current_dir().unwrap().join("input").join("input_test_2.txt")
     }
 }
 
@@ -0,0 +1,283 @@
+use std::collections::HashSet;
+use std::fmt;
+
+const DIGIT_CHARS: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NumeralError {
+    /// The base has to be at least 2 or at most -2
+    InvalidBase(i64),
+    /// The alphabet needs one distinct character per digit
+    InvalidAlphabet(String),
+    /// Only systems without a zero digit write zero as the empty string
+    Empty,
+    InvalidCharacter {
+        position: usize,
+        character: char,
+    },
+    /// A digit value outside of the alphabet was given for formatting
+    InvalidDigit(i64),
+    Overflow,
+    NotRepresentable(i128),
+}
+
+impl fmt::Display for NumeralError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NumeralError::InvalidBase(base) => write!(f, "{} is not a valid base", base),
+            NumeralError::InvalidAlphabet(alphabet) => {
+                write!(f, "{:?} is not a valid digit alphabet", alphabet)
+            }
+            NumeralError::Empty => write!(f, "empty number"),
+            NumeralError::InvalidCharacter {
+                position,
+                character,
+            } => write!(f, "invalid digit {:?} at position {}", character, position),
+            NumeralError::InvalidDigit(digit) => write!(f, "{} is not a valid digit", digit),
+            NumeralError::Overflow => write!(f, "number does not fit into an i128"),
+            NumeralError::NotRepresentable(value) => {
+                write!(f, "{} can not be written in this system", value)
+            }
+        }
+    }
+}
+
+/// Positional numeral system. The i-th character of `alphabet` stands for the
+/// digit `min_digit + i` and every position is worth `base` times the one to
+/// its right. This covers the usual bases (`min_digit` 0), balanced systems
+/// like balanced ternary or SNAFU (negative `min_digit`), bijective
+/// numeration (`min_digit` 1, there is no zero digit) and negative bases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NumeralSystem {
+    base: i64,
+    min_digit: i64,
+    alphabet: Vec<char>,
+}
+
+impl NumeralSystem {
+    pub fn new(base: i64, min_digit: i64, alphabet: &str) -> Result<Self, NumeralError> {
+        if base.abs() < 2 {
+            return Err(NumeralError::InvalidBase(base));
+        }
+        let chars: Vec<char> = alphabet.chars().collect();
+        let distinct: HashSet<&char> = chars.iter().collect();
+        if chars.len() as i64 != base.abs() || distinct.len() != chars.len() {
+            return Err(NumeralError::InvalidAlphabet(alphabet.to_string()));
+        }
+        Ok(NumeralSystem {
+            base,
+            min_digit,
+            alphabet: chars,
+        })
+    }
+
+    /// Digits `0..base` written as `0-9A-Z`.
+    pub fn standard(base: i64) -> Result<Self, NumeralError> {
+        let alphabet: String = DIGIT_CHARS
+            .chars()
+            .take(base.unsigned_abs() as usize)
+            .collect();
+        NumeralSystem::new(base, 0, &alphabet)
+    }
+
+    /// Digits `1..=base` written as `1-9A-Z`, there is no zero digit.
+    pub fn bijective(base: i64) -> Result<Self, NumeralError> {
+        let alphabet: String = DIGIT_CHARS
+            .chars()
+            .skip(1)
+            .take(base.unsigned_abs() as usize)
+            .collect();
+        NumeralSystem::new(base, 1, &alphabet)
+    }
+
+    /// Digits -1, 0 and 1 written as `-0+`.
+    pub fn balanced_ternary() -> Self {
+        NumeralSystem::new(3, -1, "-0+").unwrap()
+    }
+
+    /// Balanced base 5 with the digits -2 to 2 written as `=-012`.
+    pub fn snafu() -> Self {
+        NumeralSystem::new(5, -2, "=-012").unwrap()
+    }
+
+    pub fn base(&self) -> i64 {
+        self.base
+    }
+
+    pub fn min_digit(&self) -> i64 {
+        self.min_digit
+    }
+
+    pub fn max_digit(&self) -> i64 {
+        self.min_digit + self.alphabet.len() as i64 - 1
+    }
+
+    pub fn digit_value(&self, character: char) -> Option<i64> {
+        self.alphabet
+            .iter()
+            .position(|c| *c == character)
+            .map(|idx| self.min_digit + idx as i64)
+    }
+
+    pub fn digit_char(&self, digit: i64) -> Option<char> {
+        let idx = usize::try_from(digit - self.min_digit).ok()?;
+        self.alphabet.get(idx).copied()
+    }
+
+    fn has_zero(&self) -> bool {
+        self.min_digit <= 0 && 0 <= self.max_digit()
+    }
+
+    /// Digit values of `number`, most significant first.
+    pub fn parse_digits(&self, number: &str) -> Result<Vec<i64>, NumeralError> {
+        if number.is_empty() && self.has_zero() {
+            return Err(NumeralError::Empty);
+        }
+        number
+            .chars()
+            .enumerate()
+            .map(|(position, character)| {
+                self.digit_value(character)
+                    .ok_or(NumeralError::InvalidCharacter {
+                        position,
+                        character,
+                    })
+            })
+            .collect()
+    }
+
+    /// Writes digit values given most significant first. Leading zero digits
+    /// are kept, no digits at all is zero.
+    pub fn format_digits(&self, digits: &[i64]) -> Result<String, NumeralError> {
+        if digits.is_empty() && self.has_zero() {
+            return Ok(self.digit_char(0).unwrap().to_string());
+        }
+        digits
+            .iter()
+            .map(|digit| {
+                self.digit_char(*digit)
+                    .ok_or(NumeralError::InvalidDigit(*digit))
+            })
+            .collect()
+    }
+
+    /// Digit values of `value` without leading zeros, most significant first.
+    pub fn to_digits(&self, value: i128) -> Result<Vec<i64>, NumeralError> {
+        let base = self.base as i128;
+        let min_digit = self.min_digit as i128;
+        let bound = min_digit.abs().max(self.max_digit().abs() as i128);
+
+        // Once the remainder is within the digit range it can only cycle there,
+        // a value which revisits a remainder never reaches zero
+        let mut seen = HashSet::new();
+        let mut digits = Vec::new();
+        let mut rest = value;
+        while rest != 0 {
+            if rest.abs() <= bound && !seen.insert(rest) {
+                return Err(NumeralError::NotRepresentable(value));
+            }
+            let digit = min_digit + (rest - min_digit).rem_euclid(base.abs());
+            digits.push(digit as i64);
+            rest = (rest - digit) / base;
+        }
+        digits.reverse();
+        Ok(digits)
+    }
+
+    pub fn parse(&self, number: &str) -> Result<i128, NumeralError> {
+        let mut value: i128 = 0;
+        for digit in self.parse_digits(number)? {
+            value = value
+                .checked_mul(self.base as i128)
+                .and_then(|value| value.checked_add(digit as i128))
+                .ok_or(NumeralError::Overflow)?;
+        }
+        Ok(value)
+    }
+
+    pub fn format(&self, value: i128) -> Result<String, NumeralError> {
+        self.format_digits(&self.to_digits(value)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::numeral::{NumeralError, NumeralSystem};
+
+    #[test]
+    fn test_round_trip_in_every_kind_of_system() {
+        let systems = [
+            NumeralSystem::standard(10).unwrap(),
+            NumeralSystem::standard(16).unwrap(),
+            NumeralSystem::balanced_ternary(),
+            NumeralSystem::snafu(),
+            NumeralSystem::bijective(26).unwrap(),
+            NumeralSystem::standard(-2).unwrap(),
+            NumeralSystem::new(-10, -4, "abcdefghij").unwrap(),
+        ];
+        for system in &systems {
+            for value in -2000..=2000 {
+                match system.format(value) {
+                    Ok(number) => assert_eq!(system.parse(&number), Ok(value)),
+                    // Only systems with non negative digits and base miss values
+                    Err(error) => {
+                        assert!(value < 0 && system.base() > 0 && system.min_digit() >= 0);
+                        assert_eq!(error, NumeralError::NotRepresentable(value));
+                    }
+                }
+            }
+        }
+
+        assert_eq!(
+            NumeralSystem::standard(16).unwrap().format(255),
+            Ok("FF".into())
+        );
+        assert_eq!(
+            NumeralSystem::balanced_ternary().format(8),
+            Ok("+0-".into())
+        );
+        assert_eq!(NumeralSystem::snafu().format(2022), Ok("1=11-2".into()));
+        assert_eq!(
+            NumeralSystem::bijective(26).unwrap().format(0),
+            Ok("".into())
+        );
+        assert_eq!(
+            NumeralSystem::bijective(26).unwrap().format(36),
+            Ok("1A".into())
+        );
+        assert_eq!(
+            NumeralSystem::standard(-2).unwrap().format(-3),
+            Ok("1101".into())
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let snafu = NumeralSystem::snafu();
+        assert_eq!(
+            snafu.parse("1=-3"),
+            Err(NumeralError::InvalidCharacter {
+                position: 3,
+                character: '3'
+            })
+        );
+        assert_eq!(snafu.parse(""), Err(NumeralError::Empty));
+        assert_eq!(snafu.parse(&"2".repeat(60)), Err(NumeralError::Overflow));
+        assert_eq!(
+            snafu.format_digits(&[1, 3]),
+            Err(NumeralError::InvalidDigit(3))
+        );
+        assert_eq!(
+            NumeralSystem::new(1, 0, "0"),
+            Err(NumeralError::InvalidBase(1))
+        );
+        assert_eq!(
+            NumeralSystem::new(3, 0, "001"),
+            Err(NumeralError::InvalidAlphabet("001".into()))
+        );
+        assert_eq!(
+            NumeralSystem::standard(10).unwrap().format(-7),
+            Err(NumeralError::NotRepresentable(-7))
+        );
+    }
+}